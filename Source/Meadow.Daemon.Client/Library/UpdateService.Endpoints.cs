@@ -11,6 +11,7 @@ public partial class UpdateService
         public static string DeviceInfo => "info";
         public static string Updates => "updates";
         public static string UpdateAction => "updates/{id}";
+        public static string ConfirmUpdate => "updates/confirm";
     }
 
     private static class UpdateActions
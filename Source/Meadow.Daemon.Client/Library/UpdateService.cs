@@ -183,6 +183,38 @@ public partial class UpdateService : IUpdateService, IDisposable
         }
     }
 
+    /// <summary>
+    /// Tells the daemon this app started successfully after an update.
+    /// Required when the daemon's health_check_mode is 'confirm', otherwise the update is rolled back.
+    /// </summary>
+    /// <returns>true once the daemon has accepted the confirmation; false if it refused or couldn't be reached, in which case the caller should retry</returns>
+    public async Task<bool> ConfirmUpdate()
+    {
+        try
+        {
+            var response = await _httpClient.PostAsync(
+                $"{ApiRoot}/{Endpoints.ConfirmUpdate}",
+                null);
+
+            if (!response.IsSuccessStatusCode)
+            {
+                var resp = await response.Content.ReadAsStringAsync();
+                Console.WriteLine($"ConfirmUpdate Error ({response.StatusCode}: {resp})");
+                return false;
+            }
+
+            return true;
+        }
+        catch (Exception ex) when (ex is HttpRequestException or TaskCanceledException)
+        {
+            Console.WriteLine($"ConfirmUpdate Error: {ex.Message}");
+
+            // disconnect
+            State = UpdateState.Disconnected;
+            return false;
+        }
+    }
+
     private async Task<DeviceInfo?> GetDeviceInfo()
     {
         try
@@ -1,7 +1,34 @@
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{device_id::DeviceIdSource, rest_auth::ApiToken};
+
+/// How the daemon decides an app update came up healthy after a restart
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckMode {
+    /// No check; the update is considered good as soon as the app is started
+    None,
+    /// The app must stay running for `health_check_seconds`
+    Process,
+    /// `health_check_url` must answer with a success status within `health_check_seconds`
+    Http,
+    /// The app must call `POST /api/updates/confirm` within `health_check_seconds`
+    Confirm,
+}
+
+/// How the session key in the Meadow.Cloud login response is wrapped for the device key
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyWrapAlgorithm {
+    /// RSA PKCS#1 v1.5 encryption with an RSA device key (the original scheme)
+    RsaPkcs1,
+    /// RSA-OAEP with SHA-256 and an RSA device key
+    RsaOaep,
+}
 
 #[derive(Clone, Serialize)]
 pub struct CloudSettings {
@@ -13,7 +40,22 @@ pub struct CloudSettings {
     pub temp_extract_path: PathBuf,
     pub staging_path: PathBuf,
     pub rollback_path: PathBuf,
+    pub releases_path: PathBuf,
+    pub keep_releases: u32,
     pub rest_api_bind_address: String,
+    pub rest_api_enable_tcp: bool,
+    pub rest_api_port: u16,
+    pub rest_api_socket_path: Option<PathBuf>,
+    pub rest_api_socket_mode: u32,
+    pub rest_api_allowed_uids: Vec<u32>,
+    pub rest_api_allowed_gids: Vec<u32>,
+    pub rest_api_auth: bool,
+    pub rest_api_tokens: Vec<ApiToken>,
+    pub rest_api_token_file: Option<PathBuf>,
+    pub rest_api_tls_cert_path: Option<PathBuf>,
+    pub rest_api_tls_key_path: Option<PathBuf>,
+    pub rest_api_tls_client_ca_path: Option<PathBuf>,
+    pub ready_requires_cloud: bool,
     pub update_server_address: String,
     pub update_server_port: i32,
     pub use_authentication: bool,
@@ -23,10 +65,20 @@ pub struct CloudSettings {
     pub connect_retry_seconds: u64,
     pub update_apply_timeout_seconds: u64,
     pub auth_max_retries: u32,
+    pub auth_retry_minutes: u64,
     pub ssh_key_path: PathBuf,
+    pub device_key_path: Option<PathBuf>,
+    pub key_wrap_algorithm: KeyWrapAlgorithm,
+    pub device_id_source: DeviceIdSource,
     pub auto_download_updates: bool,
+    pub download_max_retries: u32,
+    pub require_signed_updates: bool,
+    pub trusted_signing_keys: Vec<PathBuf>,
     pub app_is_systemd_service: bool,
     pub app_service_name: Option<String>,
+    pub health_check_mode: HealthCheckMode,
+    pub health_check_seconds: u64,
+    pub health_check_url: Option<String>,
 }
 
 impl CloudSettings {
@@ -40,6 +92,17 @@ impl CloudSettings {
         }
     }
 
+    /// The private key used to authenticate with Meadow.Cloud: the dedicated device key when
+    /// one is configured, otherwise `ssh_key_path`
+    pub fn auth_key_path(&self) -> &Path {
+        self.device_key_path.as_deref().unwrap_or(&self.ssh_key_path)
+    }
+
+    /// Base URL of the Meadow.Cloud authentication server
+    pub fn auth_server_url(&self) -> &str {
+        self.auth_server_address.as_deref().unwrap_or("https://www.meadowcloud.co")
+    }
+
     pub fn default() -> CloudSettings {
         let meadow_temp = PathBuf::from("/tmp/meadow");
         CloudSettings{
@@ -51,7 +114,22 @@ impl CloudSettings {
             temp_extract_path: meadow_temp.join("update"),
             staging_path: meadow_temp.join("staging"),
             rollback_path: meadow_temp.join("rollback"),
+            releases_path: PathBuf::from("/var/lib/meadow/releases"),
+            keep_releases: 0,  // Single rollback directory by default for backward compatibility
             rest_api_bind_address: "127.0.0.1".to_string(),  // Localhost only for security
+            rest_api_enable_tcp: true,
+            rest_api_port: 5000,
+            rest_api_socket_path: None,  // No Unix socket by default
+            rest_api_socket_mode: 0o660,
+            rest_api_allowed_uids: Vec::new(),
+            rest_api_allowed_gids: Vec::new(),
+            rest_api_auth: false,  // Disabled by default for backward compatibility
+            rest_api_tokens: Vec::new(),
+            rest_api_token_file: None,
+            rest_api_tls_cert_path: None,  // Plain HTTP unless a certificate is configured
+            rest_api_tls_key_path: None,
+            rest_api_tls_client_ca_path: None,
+            ready_requires_cloud: false,
             update_server_address: "".to_string(),
             update_server_port: 883,
             use_authentication: true,
@@ -61,10 +139,20 @@ impl CloudSettings {
             connect_retry_seconds: 15,
             update_apply_timeout_seconds: 300,  // 5 minutes
             auth_max_retries: 10,  // Max 10 authentication attempts before failing
+            auth_retry_minutes: 0,  // Wait for a manual reconnect once authentication has failed
             ssh_key_path: Self::get_default_ssh_key_path(),
+            device_key_path: None,  // Use ssh_key_path unless a dedicated device key is configured
+            key_wrap_algorithm: KeyWrapAlgorithm::RsaPkcs1,  // What Meadow.Cloud has always used
+            device_id_source: DeviceIdSource::MachineId,
             auto_download_updates: false,  // Disabled by default for backward compatibility
+            download_max_retries: 5,  // Resume an interrupted download up to 5 times
+            require_signed_updates: false,  // Disabled by default for backward compatibility
+            trusted_signing_keys: Vec::new(),
             app_is_systemd_service: false,  // Direct process spawn by default
             app_service_name: None,  // No service name by default
+            health_check_mode: HealthCheckMode::None,  // Disabled by default for backward compatibility
+            health_check_seconds: 30,
+            health_check_url: None,
         }
     }
 
@@ -146,10 +234,96 @@ impl CloudSettings {
                     {
                         settings.temp_extract_path = PathBuf::from(val);
                     },
+                    "releases_path" =>
+                    {
+                        settings.releases_path = PathBuf::from(val);
+                    },
+                    "keep_releases" =>
+                    {
+                        settings.keep_releases = val.parse::<u32>()
+                            .unwrap_or_else(|e| {
+                                println!("WARNING: Invalid keep_releases '{}': {}. Using default.", val, e);
+                                CloudSettings::default().keep_releases
+                            });
+                    },
                     "rest_api_bind_address" =>
                     {
                         settings.rest_api_bind_address = val.into();
                     },
+                    "rest_api_enable_tcp" =>
+                    {
+                        settings.rest_api_enable_tcp = val.to_lowercase() == "yes";
+                    },
+                    "rest_api_port" =>
+                    {
+                        settings.rest_api_port = val.parse::<u16>()
+                            .unwrap_or_else(|e| {
+                                println!("WARNING: Invalid rest_api_port '{}': {}. Using default.", val, e);
+                                CloudSettings::default().rest_api_port
+                            });
+                    },
+                    "rest_api_socket_path" =>
+                    {
+                        if !val.is_empty() {
+                            settings.rest_api_socket_path = Some(PathBuf::from(val));
+                        }
+                    },
+                    "rest_api_socket_mode" =>
+                    {
+                        settings.rest_api_socket_mode = u32::from_str_radix(val.trim_start_matches("0o"), 8)
+                            .unwrap_or_else(|e| {
+                                println!("WARNING: Invalid rest_api_socket_mode '{}': {}. Using default.", val, e);
+                                CloudSettings::default().rest_api_socket_mode
+                            });
+                    },
+                    "rest_api_allowed_uids" =>
+                    {
+                        settings.rest_api_allowed_uids = Self::parse_ids("rest_api_allowed_uids", val);
+                    },
+                    "rest_api_allowed_gids" =>
+                    {
+                        settings.rest_api_allowed_gids = Self::parse_ids("rest_api_allowed_gids", val);
+                    },
+                    "rest_api_auth" =>
+                    {
+                        settings.rest_api_auth = val.to_lowercase() == "yes";
+                    },
+                    "rest_api_token" =>
+                    {
+                        // may be given more than once
+                        match ApiToken::parse(val) {
+                            Ok(token) => settings.rest_api_tokens.push(token),
+                            Err(e) => println!("WARNING: Invalid rest_api_token: {}. Skipping.", e),
+                        }
+                    },
+                    "rest_api_token_file" =>
+                    {
+                        if !val.is_empty() {
+                            settings.rest_api_token_file = Some(PathBuf::from(val));
+                        }
+                    },
+                    "rest_api_tls_cert_path" =>
+                    {
+                        if !val.is_empty() {
+                            settings.rest_api_tls_cert_path = Some(PathBuf::from(val));
+                        }
+                    },
+                    "rest_api_tls_key_path" =>
+                    {
+                        if !val.is_empty() {
+                            settings.rest_api_tls_key_path = Some(PathBuf::from(val));
+                        }
+                    },
+                    "rest_api_tls_client_ca_path" =>
+                    {
+                        if !val.is_empty() {
+                            settings.rest_api_tls_client_ca_path = Some(PathBuf::from(val));
+                        }
+                    },
+                    "ready_requires_cloud" =>
+                    {
+                        settings.ready_requires_cloud = val.to_lowercase() == "yes";
+                    },
                     "update_server_address" =>
                     {
                         settings.update_server_address = val.into();
@@ -206,14 +380,67 @@ impl CloudSettings {
                                 CloudSettings::default().auth_max_retries
                             });
                     },
+                    "auth_retry_minutes" =>
+                    {
+                        settings.auth_retry_minutes = val.parse::<u64>()
+                            .unwrap_or_else(|e| {
+                                println!("WARNING: Invalid auth_retry_minutes '{}': {}. Using default.", val, e);
+                                CloudSettings::default().auth_retry_minutes
+                            });
+                    },
                     "ssh_key_path" =>
                     {
                         settings.ssh_key_path = PathBuf::from(val);
                     },
+                    "device_key_path" =>
+                    {
+                        if !val.is_empty() {
+                            settings.device_key_path = Some(PathBuf::from(val));
+                        }
+                    },
+                    "device_id_source" =>
+                    {
+                        settings.device_id_source = DeviceIdSource::parse(val)
+                            .unwrap_or_else(|| {
+                                println!("WARNING: Invalid device_id_source '{}'. Using default.", val);
+                                CloudSettings::default().device_id_source
+                            });
+                    },
+                    "key_wrap_algorithm" =>
+                    {
+                        settings.key_wrap_algorithm = match val.to_lowercase().as_str() {
+                            "rsa-pkcs1" => KeyWrapAlgorithm::RsaPkcs1,
+                            "rsa-oaep" => KeyWrapAlgorithm::RsaOaep,
+                            _ => {
+                                println!("WARNING: Invalid key_wrap_algorithm '{}'. Using default.", val);
+                                CloudSettings::default().key_wrap_algorithm
+                            }
+                        };
+                    },
                     "auto_download_updates" =>
                     {
                         settings.auto_download_updates = val.to_lowercase() == "yes";
                     },
+                    "download_max_retries" =>
+                    {
+                        settings.download_max_retries = val.parse::<u32>()
+                            .unwrap_or_else(|e| {
+                                println!("WARNING: Invalid download_max_retries '{}': {}. Using default.", val, e);
+                                CloudSettings::default().download_max_retries
+                            });
+                    },
+                    "require_signed_updates" =>
+                    {
+                        settings.require_signed_updates = val.to_lowercase() == "yes";
+                    },
+                    "trusted_signing_keys" =>
+                    {
+                        settings.trusted_signing_keys = val.split(';')
+                            .map(str::trim)
+                            .filter(|p| !p.is_empty())
+                            .map(PathBuf::from)
+                            .collect();
+                    },
                     "app_is_systemd_service" =>
                     {
                         settings.app_is_systemd_service = val.to_lowercase() == "yes";
@@ -224,6 +451,33 @@ impl CloudSettings {
                             settings.app_service_name = Some(val.to_string());
                         }
                     },
+                    "health_check_mode" =>
+                    {
+                        settings.health_check_mode = match val.to_lowercase().as_str() {
+                            "none" => HealthCheckMode::None,
+                            "process" => HealthCheckMode::Process,
+                            "http" => HealthCheckMode::Http,
+                            "confirm" => HealthCheckMode::Confirm,
+                            _ => {
+                                println!("WARNING: Invalid health_check_mode '{}'. Using default.", val);
+                                CloudSettings::default().health_check_mode
+                            }
+                        };
+                    },
+                    "health_check_seconds" =>
+                    {
+                        settings.health_check_seconds = val.parse::<u64>()
+                            .unwrap_or_else(|e| {
+                                println!("WARNING: Invalid health_check_seconds '{}': {}. Using default.", val, e);
+                                CloudSettings::default().health_check_seconds
+                            });
+                    },
+                    "health_check_url" =>
+                    {
+                        if !val.is_empty() {
+                            settings.health_check_url = Some(val.to_string());
+                        }
+                    },
                     _ =>
                     {
                         println!("WARNING: unknown setting '{}'", s);
@@ -269,6 +523,16 @@ impl CloudSettings {
         }
     }
 
+    fn parse_ids(key: &str, val: &str) -> Vec<u32> {
+        val.split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .filter_map(|id| id.parse::<u32>()
+                .map_err(|e| println!("WARNING: Invalid {} entry '{}': {}. Skipping.", key, id, e))
+                .ok())
+            .collect()
+    }
+
     fn read_lines(filename: &str) -> Result<Vec<String>> {
         let contents = read_to_string(filename)
             .with_context(|| format!("Failed to read file: {}", filename))?;
@@ -1,38 +1,57 @@
 
 extern crate paho_mqtt as mqtt;
 
-use std::{ process, thread, time::Duration, sync::mpsc::Sender};
-use crate::{update_parser::UpdateParser, cloud_settings::CloudSettings, update_service::UpdateState, update_descriptor::UpdateDescriptor};
+use std::{ process, thread, time::Duration, sync::{Arc, Mutex, mpsc::{Receiver, Sender}}};
+use crate::{device_id::DeviceId, update_parser::UpdateParser, cloud_settings::CloudSettings, update_service::{ServiceStatus, UpdateState}, update_descriptor::UpdateDescriptor, metrics::{Metrics, metrics}};
 
 const DFLT_CLIENT:&str = "mc_daemon";
 
 
 pub struct CloudSubscriber {
     settings: CloudSettings,
-    machine_id: String,
-    oid: String
+    device_id: DeviceId,
+    oid: String,
+    status: Arc<Mutex<ServiceStatus>>
 }
 
 impl CloudSubscriber {
+    const TOKEN_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
     pub fn new(settings: CloudSettings, 
-               machine_id: String,
-               oid: String
+               device_id: DeviceId,
+               oid: String,
+               status: Arc<Mutex<ServiceStatus>>
                 ) -> CloudSubscriber {
-        CloudSubscriber { settings, machine_id, oid }
+        CloudSubscriber { settings, device_id, oid, status }
+    }
+
+    fn update_status<F: FnOnce(&mut ServiceStatus)>(&self, change: F) {
+        match self.status.lock() {
+            Ok(mut s) => change(&mut s),
+            Err(e) => println!("WARNING: Failed to lock service status: {}", e)
+        }
     }
 
     // Reconnect to the broker when connection is lost.
     fn try_reconnect(&self, cli: &mqtt::Client) -> bool
     {
         println!("Connection lost. Waiting to retry connection");
+        self.update_status(|s| {
+            s.set_broker_connected(false);
+            s.set_error("Lost connection to the MQTT broker");
+        });
         for _ in 0..12 {
             thread::sleep(Duration::from_secs(self.settings.connect_retry_seconds));
-            if cli.reconnect().is_ok() {
+            let reconnected = cli.reconnect().is_ok();
+            Metrics::record(&metrics().mqtt_reconnects, reconnected);
+            if reconnected {
                 println!("Successfully reconnected");
+                self.update_status(|s| s.set_broker_connected(true));
                 return true;
             }
         }
         println!("Unable to reconnect after several attempts.");
+        self.update_status(|s| s.set_error("Unable to reconnect to the MQTT broker"));
         false
     }
 
@@ -42,7 +61,7 @@ impl CloudSubscriber {
         for topic in topics {
             // do macro substitution
             let topic = topic
-                .replace("{ID}", &self.machine_id)
+                .replace("{ID}", self.device_id.as_str())
                 .replace("{OID}", &self.oid)
                 .trim()
                 .to_string();
@@ -64,7 +83,7 @@ impl CloudSubscriber {
         for topic in topics {
             // do macro substitution
             let t = topic
-                .replace("{ID}", &self.machine_id)
+                .replace("{ID}", self.device_id.as_str())
                 .replace("{OID}", &self.oid);
 
             println!("Unssubscribing from {}", t);
@@ -75,29 +94,8 @@ impl CloudSubscriber {
         }
     }
 
-    pub fn start(&mut self, sender: Sender<UpdateDescriptor>, state_sender: Sender<UpdateState>, jwt: String, oid: String) { 
-        let host = format!("{}:{}", self.settings.update_server_address, self.settings.update_server_port);
-        self.oid = oid;
-
-        // Define the set of options for the create.
-        // Use an ID for a persistent session.
-        let create_opts = mqtt::CreateOptionsBuilder::new()
-            .server_uri(&host)
-            .mqtt_version(mqtt::MQTT_VERSION_5)
-            .client_id(DFLT_CLIENT.to_string())
-            .finalize();
-
-        // Create a client.
-        let client = mqtt::Client::new(create_opts)
-            .unwrap_or_else(|err| {
-                println!("Error creating the client: {:?}", err);
-                process::exit(1);
-        });
-
-        // Initialize the consumer before connecting.
-        let receiver = client.start_consuming();
-
-        // Define the set of options for the connection.
+    // Builds the connection options, with the JWT as the password.
+    fn connect_options(&self, jwt: String) -> mqtt::ConnectOptions {
         let lwt = mqtt::MessageBuilder::new()
             .topic("test")
             .payload("Consumer lost connection")
@@ -109,29 +107,76 @@ impl CloudSubscriber {
         }
         let ssl_options = ssl_builder.finalize();
 
-        let conn_opts = mqtt::ConnectOptionsBuilder::new()
+        mqtt::ConnectOptionsBuilder::new()
             .keep_alive_interval(Duration::from_secs(20))
             .clean_session(false)
             .will_message(lwt)
-            .user_name(self.machine_id.clone())
+            .user_name(self.device_id.to_string())
             .password(jwt)
             .ssl_options(ssl_options)
-            .finalize();
+            .finalize()
+    }
 
+    // Connects to the broker, retrying until it succeeds.
+    fn connect(&self, cli: &mqtt::Client, conn_opts: &mqtt::ConnectOptions, host: &str) {
         loop {
             println!("making MQTT connection to {}:\n", host);
-            match client.connect(conn_opts.clone()) {
+            match cli.connect(conn_opts.clone()) {
                 Ok(response) => {
                     println!("MQTT connection succeeded! Response: {:?}", response);
+                    Metrics::record(&metrics().mqtt_connects, true);
+                    self.update_status(|s| s.set_broker_connected(true));
                     break;
                 },
                 Err(e) => {
                     println!("MQTT connection failed: {}\n", e);
+                    Metrics::record(&metrics().mqtt_connects, false);
+                    self.update_status(|s| s.set_error(format!("MQTT connection to {} failed: {}", host, e)));
                     println!("Retrying in {} seconds...", self.settings.connect_retry_seconds);
                     thread::sleep(Duration::from_secs(self.settings.connect_retry_seconds));
                 }
             }
         }
+    }
+
+    // Reconnects with a refreshed JWT, since the broker checks it only when connecting.
+    fn reconnect_with_token(&self, cli: &mqtt::Client, jwt: String, host: &str) {
+        println!("Reconnecting to the MQTT broker with a refreshed token");
+
+        if cli.is_connected() && let Err(e) = cli.disconnect(None) {
+            println!("WARNING: Failed to disconnect cleanly: {}", e);
+        }
+        self.update_status(|s| s.set_broker_connected(false));
+
+        self.connect(cli, &self.connect_options(jwt), host);
+        self.subscribe_topics(cli, &self.settings.mqtt_topics);
+    }
+
+    pub fn start(&mut self, sender: Sender<UpdateDescriptor>, state_sender: Sender<UpdateState>, jwt: String, oid: String, tokens: Receiver<String>) { 
+        let host = format!("{}:{}", self.settings.update_server_address, self.settings.update_server_port);
+        self.oid = oid;
+
+        // Define the set of options for the create.
+        // Use an ID for a persistent session.
+        let create_opts = mqtt::CreateOptionsBuilder::new()
+            .server_uri(&host)
+            .mqtt_version(mqtt::MQTT_VERSION_5)
+            .client_id(DFLT_CLIENT.to_string())
+            .finalize();
+
+        // Create a client.
+        let client = mqtt::Client::new(create_opts)
+            .unwrap_or_else(|err| {
+                println!("Error creating the client: {:?}", err);
+                process::exit(1);
+        });
+
+        // Initialize the consumer before connecting.
+        let receiver = client.start_consuming();
+
+        // Define the set of options for the connection.
+        let conn_opts = self.connect_options(jwt);
+        self.connect(&client, &conn_opts, &host);
 
         println!("Sending Connected state to UpdateService...");
         if let Err(e) = state_sender.send(UpdateState::Connected) {
@@ -147,12 +192,15 @@ impl CloudSubscriber {
         println!("Subscribed to topics: {:?}", self.settings.mqtt_topics);
 
         loop {
-            match receiver.recv() {
+            // wake up now and then to pick up refreshed tokens
+            match receiver.recv_timeout(Self::TOKEN_CHECK_INTERVAL) {
                 Ok(Some(msg)) => {
                     println!("\n>>> MQTT MESSAGE RECEIVED <<<");
                     println!("Topic: {}", msg.topic());
                     println!("Payload: {}", msg.payload_str());
                     println!("QoS: {:?}", msg.qos());
+                    self.update_status(|s| s.message_received());
+                    metrics().mqtt_messages.inc();
 
                     // Process the message here
                     match UpdateParser::parse_message(msg.payload_str().as_ref()) {
@@ -167,6 +215,7 @@ impl CloudSubscriber {
                         }
                         Err(e) => {
                             println!("ERROR: Failed to parse update message: {}", e);
+                            self.update_status(|s| s.set_error(format!("Failed to parse update message: {}", e)));
                         }
                     }
                 }
@@ -182,11 +231,17 @@ impl CloudSubscriber {
                         }
                     }
                 }
+                Err(err) if err.is_timeout() => { /* nothing yet; check for a new token below */ }
                 Err(err) => {
                     println!("Error receiving message: {:?}", err);
+                    self.update_status(|s| s.set_error(format!("Error receiving MQTT message: {:?}", err)));
                     break; // Optionally break out of the loop on error
                 }
             }
+
+            if let Some(jwt) = tokens.try_iter().last() {
+                self.reconnect_with_token(&client, jwt, &host);
+            }
         }
 
         for msg in receiver.iter() {
@@ -198,6 +253,8 @@ impl CloudSubscriber {
             }
         }
 
+        self.update_status(|s| s.set_broker_connected(false));
+
         // If still connected, then disconnect now.
         if client.is_connected() {
             println!("Disconnecting");
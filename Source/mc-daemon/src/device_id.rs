@@ -0,0 +1,118 @@
+use std::{fmt, fs, path::{Path, PathBuf}};
+use serde::{Serialize, Serializer};
+
+// dbus first: earlier releases authenticated with that ID, and where the two files differ
+// switching would change the device's identity under it
+const MACHINE_ID_PATHS: [&str; 2] = ["/var/lib/dbus/machine-id", "/etc/machine-id"];
+const DMI_SERIAL_PATH: &str = "/sys/class/dmi/id/product_serial";
+
+/// Where the daemon gets the ID it uses with Meadow.Cloud
+///
+/// Written in meadow.conf as `machine-id`, `dmi`, `file:<path>`, `env:<variable>` or
+/// `value:<id>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeviceIdSource {
+    /// /var/lib/dbus/machine-id, falling back to /etc/machine-id
+    MachineId,
+    /// The first line of a file
+    File(PathBuf),
+    /// The DMI product serial number
+    DmiSerial,
+    /// An environment variable
+    Env(String),
+    /// An explicit ID
+    Value(String),
+}
+
+impl DeviceIdSource {
+    pub fn parse(val: &str) -> Option<DeviceIdSource> {
+        let val = val.trim();
+        match val.split_once(':') {
+            Some(("file", path)) if !path.trim().is_empty() => Some(DeviceIdSource::File(PathBuf::from(path.trim()))),
+            Some(("env", name)) if !name.trim().is_empty() => Some(DeviceIdSource::Env(name.trim().to_string())),
+            Some(("value", id)) if !id.trim().is_empty() => Some(DeviceIdSource::Value(id.trim().to_string())),
+            Some(_) => None,
+            None => match val.to_lowercase().as_str() {
+                "machine-id" => Some(DeviceIdSource::MachineId),
+                "dmi" => Some(DeviceIdSource::DmiSerial),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for DeviceIdSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceIdSource::MachineId => write!(f, "machine-id"),
+            DeviceIdSource::File(path) => write!(f, "file:{}", path.display()),
+            DeviceIdSource::DmiSerial => write!(f, "dmi"),
+            DeviceIdSource::Env(name) => write!(f, "env:{}", name),
+            DeviceIdSource::Value(id) => write!(f, "value:{}", id),
+        }
+    }
+}
+
+impl Serialize for DeviceIdSource {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The device's ID, resolved once at startup and shared by authentication, MQTT and the REST API
+///
+/// IDs are trimmed and upper-cased, as Meadow.Cloud expects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceId(String);
+
+impl DeviceId {
+    pub fn resolve(source: &DeviceIdSource) -> Result<DeviceId, String> {
+        let id = match source {
+            DeviceIdSource::MachineId => MACHINE_ID_PATHS.iter()
+                .find_map(|path| Self::read_id(Path::new(path)).ok())
+                .ok_or_else(|| format!("No machine-id found in {}", MACHINE_ID_PATHS.join(" or ")))?,
+            DeviceIdSource::File(path) => Self::read_id(path)?,
+            DeviceIdSource::DmiSerial => Self::read_id(Path::new(DMI_SERIAL_PATH))?,
+            DeviceIdSource::Env(name) => std::env::var(name)
+                .map_err(|e| format!("Failed to read device ID from ${}: {}", name, e))?,
+            DeviceIdSource::Value(id) => id.clone(),
+        };
+
+        let id = id.trim().to_uppercase();
+        if id.is_empty() {
+            return Err(format!("Device ID from {} is empty", source));
+        }
+        Ok(DeviceId(id))
+    }
+
+    /// Stand-in for when the configured source can't be read
+    pub fn from_hostname() -> DeviceId {
+        let host = fs::read_to_string("/etc/hostname")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| "unknown-host".to_string());
+        DeviceId(host.to_uppercase())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn read_id(path: &Path) -> Result<String, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read device ID from {:?}: {}", path, e))?;
+        let id = contents.lines().next().unwrap_or("").trim();
+        if id.is_empty() {
+            return Err(format!("Device ID file {:?} is empty", path));
+        }
+        Ok(id.to_string())
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,75 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+use crate::{update_descriptor::UpdateDescriptor, update_service::UpdateState, update_store::DownloadProgress};
+
+/// Something that happened in the daemon that a connected app may want to react to
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DaemonEvent {
+    /// The cloud update service moved to a new state
+    StateChanged {
+        state: UpdateState,
+    },
+    /// A new update descriptor arrived
+    UpdateAdded {
+        update: UpdateDescriptor,
+    },
+    /// An update moved through its lifecycle (downloaded, staged, applied, failed, ...)
+    UpdateChanged {
+        update: UpdateDescriptor,
+    },
+    /// More of an update package has been received
+    DownloadProgress {
+        #[serde(rename = "mpakId")]
+        mpak_id: String,
+        #[serde(flatten)]
+        progress: DownloadProgress,
+    },
+}
+
+impl DaemonEvent {
+    /// The event name, as used for the `event:` field of the stream
+    pub fn name(&self) -> &'static str {
+        match self {
+            DaemonEvent::StateChanged { .. } => "stateChanged",
+            DaemonEvent::UpdateAdded { .. } => "updateAdded",
+            DaemonEvent::UpdateChanged { .. } => "updateChanged",
+            DaemonEvent::DownloadProgress { .. } => "downloadProgress",
+        }
+    }
+}
+
+/// Fan-out of `DaemonEvent`s to whoever is listening
+///
+/// Publishing never blocks; a subscriber that falls more than `CAPACITY` events behind
+/// skips the ones it missed.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: Sender<DaemonEvent>,
+}
+
+impl EventBus {
+    const CAPACITY: usize = 256;
+
+    pub fn new() -> EventBus {
+        let (sender, _) = broadcast::channel(Self::CAPACITY);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: DaemonEvent) {
+        // an error only means nobody is listening right now
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> Receiver<DaemonEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,129 @@
+//! Liveness and readiness checks behind `/healthz` and `/readyz`
+//!
+//! Both are meant for systemd and container probes, so they stay cheap: no machine-id, no key
+//! lookups, no shelling out.
+
+use std::{fs, path::Path, time::Duration};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{cloud_settings::CloudSettings, update_service::{ServiceStatus, UpdateService}};
+
+/// How long the service loop may go without a heartbeat before it counts as stuck
+///
+/// Backoffs heartbeat every second, so the longest legitimate gap is one login or token refresh
+/// running into its request timeout; the rest is slack for the loop's own sleep and for
+/// background downloads sharing its thread.
+const SERVICE_STALL_SECONDS: u64 = UpdateService::AUTH_REQUEST_TIMEOUT_SECONDS + 10;
+
+const PROBE_FILE_NAME: &str = ".readyz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Failing,
+    /// Not running by configuration; doesn't count against readiness
+    Disabled,
+}
+
+/// One component's part of a readiness check
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReadinessComponents {
+    /// update_store_path can be written to
+    #[serde(rename = "updateStore")]
+    pub update_store: ComponentHealth,
+    /// The update service loop is still coming round
+    #[serde(rename = "updateService")]
+    pub update_service: ComponentHealth,
+    /// Connected to Meadow.Cloud; only held against readiness with ready_requires_cloud
+    pub cloud: ComponentHealth,
+}
+
+/// Body of `/healthz` and `/readyz`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub components: Option<ReadinessComponents>,
+}
+
+impl ComponentHealth {
+    fn ok(detail: Option<String>) -> ComponentHealth {
+        ComponentHealth { status: HealthStatus::Ok, detail }
+    }
+
+    fn failing(detail: impl Into<String>) -> ComponentHealth {
+        ComponentHealth { status: HealthStatus::Failing, detail: Some(detail.into()) }
+    }
+
+    fn disabled(detail: impl Into<String>) -> ComponentHealth {
+        ComponentHealth { status: HealthStatus::Disabled, detail: Some(detail.into()) }
+    }
+}
+
+impl HealthResponse {
+    /// Answering at all is proof enough that the process and its REST loop are alive
+    pub fn live() -> HealthResponse {
+        HealthResponse { status: HealthStatus::Ok, components: None }
+    }
+
+    /// Check each component the daemon needs to do its job
+    pub fn ready(settings: &CloudSettings, service: &ServiceStatus) -> HealthResponse {
+        let update_store = Self::check_writable(&settings.update_store_path);
+        let (update_service, cloud) = if settings.enable_mqtt_listener {
+            (Self::check_service(service), Self::check_cloud(service))
+        } else {
+            let disabled = || ComponentHealth::disabled("enable_mqtt_listener is off");
+            (disabled(), disabled())
+        };
+
+        let failing = update_store.status == HealthStatus::Failing
+            || update_service.status == HealthStatus::Failing
+            || (settings.ready_requires_cloud && cloud.status == HealthStatus::Failing);
+
+        HealthResponse {
+            status: if failing { HealthStatus::Failing } else { HealthStatus::Ok },
+            components: Some(ReadinessComponents { update_store, update_service, cloud }),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == HealthStatus::Ok
+    }
+
+    fn check_writable(dir: &Path) -> ComponentHealth {
+        let probe = dir.join(PROBE_FILE_NAME);
+        match fs::write(&probe, b"") {
+            Ok(()) => {
+                let _ = fs::remove_file(&probe);
+                ComponentHealth::ok(None)
+            },
+            Err(e) => ComponentHealth::failing(format!("Can't write to {}: {}", dir.display(), e)),
+        }
+    }
+
+    fn check_service(service: &ServiceStatus) -> ComponentHealth {
+        match service.since_heartbeat() {
+            None => ComponentHealth::failing("Update service has not started"),
+            Some(age) if age > Duration::from_secs(SERVICE_STALL_SECONDS) =>
+                ComponentHealth::failing(format!("Update service last ran {} seconds ago", age.as_secs())),
+            Some(_) => ComponentHealth::ok(Some(format!("{:?}", service.state))),
+        }
+    }
+
+    fn check_cloud(service: &ServiceStatus) -> ComponentHealth {
+        if service.broker_connected {
+            ComponentHealth::ok(None)
+        } else {
+            ComponentHealth::failing(service.last_error.clone().unwrap_or_else(|| format!("Service is {:?}", service.state)))
+        }
+    }
+}
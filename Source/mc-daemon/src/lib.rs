@@ -1,8 +1,19 @@
 pub mod update_descriptor;
 pub mod update_store;
+pub mod update_error;
 pub mod cloud_settings;
 pub mod cloud_subscriber;
 pub mod update_parser;
 pub mod update_service;
 pub mod rest_server;
-pub mod crypto;
\ No newline at end of file
+pub mod rest_auth;
+pub mod rest_error;
+pub mod rest_openapi;
+pub mod rest_tls;
+pub mod crypto;
+pub mod device_id;
+pub mod package_verifier;
+pub mod release_store;
+pub mod events;
+pub mod metrics;
+pub mod health;
\ No newline at end of file
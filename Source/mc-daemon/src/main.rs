@@ -1,5 +1,5 @@
-use std::{fs::read_to_string, sync::{Arc, Mutex}};
-use mc_daemon::{cloud_settings::CloudSettings, update_service::UpdateService, rest_server, update_store::UpdateStore};
+use std::sync::{Arc, Mutex};
+use mc_daemon::{cloud_settings::CloudSettings, crypto::Crypto, device_id::{DeviceId, DeviceIdSource}, update_service::{ServiceStatus, UpdateService}, rest_server, update_store::UpdateStore};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -7,15 +7,22 @@ async fn main() -> std::io::Result<()> {
 
     let settings = CloudSettings::from_file("/etc/meadow.conf");
 
-    // Try to read machine ID from standard locations
-    let machine_id = read_to_string("/etc/machine-id")
-        .or_else(|_| read_to_string("/var/lib/dbus/machine-id"))
-        .unwrap_or_else(|e| {
-            eprintln!("WARNING: Failed to read machine-id: {}. Using hostname.", e);
-            std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown-host".to_string())
-        })
-        .trim()
-        .to_string();
+    // Resolve the device ID once; authentication, MQTT and the REST API all use this one.
+    // Only the default source falls back to the hostname: one that was configured on purpose
+    // must not be quietly swapped for an ID the device was never registered under.
+    let device_id = match DeviceId::resolve(&settings.device_id_source) {
+        Ok(device_id) => device_id,
+        Err(e) if settings.device_id_source == DeviceIdSource::MachineId => {
+            eprintln!("WARNING: {}. Using hostname.", e);
+            DeviceId::from_hostname()
+        },
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            eprintln!("Please fix device_id_source ({}) in /etc/meadow.conf.", settings.device_id_source);
+            return Err(std::io::Error::other(e));
+        }
+    };
+    println!("Device ID: {} (from {})", device_id, settings.device_id_source);
 
     // Ensure meadow_root directory exists
     if !settings.meadow_root.exists() {
@@ -75,13 +82,24 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    // Generate the dedicated device key on first start
+    if let Some(key_path) = &settings.device_key_path
+        && !key_path.exists() {
+        println!("Generating device key: {:?}", key_path);
+        match Crypto::generate_private_key(key_path) {
+            Ok(()) => println!("Device key created; provision it with POST /api/provision"),
+            Err(e) => eprintln!("ERROR: Failed to generate device key: {:#}", e),
+        }
+    }
+
     println!("Creating update store...");
     let update_store: Arc<Mutex<UpdateStore>> = Arc::new(Mutex::new(UpdateStore::new(settings.clone())));
 
     // Conditionally start the MQTT listener based on configuration
-    if settings.enable_mqtt_listener {
+    let service_status = if settings.enable_mqtt_listener {
         println!("MQTT listener enabled - creating update service...");
-        let mut update_service = UpdateService::new(settings.clone(), machine_id.clone(), update_store.clone());
+        let mut update_service = UpdateService::new(settings.clone(), device_id.clone(), update_store.clone());
+        let service_status = update_service.status();
 
         println!("Spawning UpdateService in background thread...");
         std::thread::spawn(move || {
@@ -98,15 +116,18 @@ async fn main() -> std::io::Result<()> {
                 println!("UpdateService task ended!");
             });
         });
+
+        service_status
     } else {
         println!("MQTT listener disabled - external application will handle subscriptions and downloads");
-    }
+        Arc::new(Mutex::new(ServiceStatus::new()))
+    };
 
     println!("Creating REST server...");
     let mut rest_server = rest_server::RestServer::new();
 
     println!("Starting REST server in main thread...");
-    match rest_server.start(update_store, settings.clone(), &settings.rest_api_bind_address).await {
+    match rest_server.start(update_store, service_status, device_id, settings.clone(), &settings.rest_api_bind_address).await {
         Ok(_) => {
             println!("REST server stopped");
             Ok(())
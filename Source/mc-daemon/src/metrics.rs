@@ -0,0 +1,171 @@
+//! Prometheus metrics for the daemon, served in text format at `/metrics`
+//!
+//! Everything is registered once in a process-wide `Metrics`; call `metrics()` from wherever
+//! something worth counting happens.
+
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use actix_web::{Error, body::MessageBody, dev::{ServiceRequest, ServiceResponse}, middleware::Next};
+use prometheus::{Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+
+const NAMESPACE: &str = "meadow_daemon";
+
+pub struct Metrics {
+    registry: Registry,
+    /// Finished downloads by `result` (`succeeded` or `failed`)
+    pub downloads: IntCounterVec,
+    pub download_bytes: IntCounter,
+    pub download_retries: IntCounter,
+    /// Packages rejected after download, by `kind` (`package` or `signature`)
+    pub verify_failures: IntCounterVec,
+    /// Finished applies by `result` (`succeeded` or `failed`)
+    pub applies: IntCounterVec,
+    /// From the apply request to the new version passing its health check, including waiting for the app to exit
+    pub apply_duration: Histogram,
+    /// App directory swaps by `mode` (`atomic`, `file_by_file` or `release`)
+    pub swaps: IntCounterVec,
+    /// Rollbacks by `reason` (`health_check`, `requested` or `release_switch`)
+    pub rollbacks: IntCounterVec,
+    /// Meadow.Cloud authentication attempts by `result`
+    pub auth_attempts: IntCounterVec,
+    /// MQTT connects by `result`
+    pub mqtt_connects: IntCounterVec,
+    /// MQTT reconnects after a lost connection, by `result`
+    pub mqtt_reconnects: IntCounterVec,
+    pub mqtt_messages: IntCounter,
+    /// REST requests by `method`, `route` and `status`
+    pub http_requests: IntCounterVec,
+    /// REST request latency by `method` and `route`
+    pub http_request_duration: HistogramVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// The daemon's metrics
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new();
+
+        Metrics {
+            downloads: Self::counter_vec(&registry, "downloads_total", "Update downloads finished", &["result"]),
+            download_bytes: Self::counter(&registry, "download_bytes_total", "Update package bytes received"),
+            download_retries: Self::counter(&registry, "download_retries_total", "Update download attempts retried"),
+            verify_failures: Self::counter_vec(&registry, "verify_failures_total", "Downloaded packages that failed verification", &["kind"]),
+            applies: Self::counter_vec(&registry, "applies_total", "Update applies finished", &["result"]),
+            apply_duration: Self::histogram(&registry, "apply_duration_seconds", "Time from an apply request to the new version passing its health check",
+                vec![0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0]),
+            swaps: Self::counter_vec(&registry, "swaps_total", "App directory swaps", &["mode"]),
+            rollbacks: Self::counter_vec(&registry, "rollbacks_total", "Rollbacks to a previous app version", &["reason"]),
+            auth_attempts: Self::counter_vec(&registry, "auth_attempts_total", "Meadow.Cloud authentication attempts", &["result"]),
+            mqtt_connects: Self::counter_vec(&registry, "mqtt_connects_total", "MQTT broker connection attempts", &["result"]),
+            mqtt_reconnects: Self::counter_vec(&registry, "mqtt_reconnects_total", "MQTT broker reconnection attempts", &["result"]),
+            mqtt_messages: Self::counter(&registry, "mqtt_messages_total", "MQTT messages received"),
+            http_requests: Self::counter_vec(&registry, "http_requests_total", "REST API requests", &["method", "route", "status"]),
+            http_request_duration: Self::histogram_vec(&registry, "http_request_duration_seconds", "REST API request latency", &["method", "route"]),
+            registry,
+        }
+    }
+
+    fn counter(registry: &Registry, name: &str, help: &str) -> IntCounter {
+        let counter = IntCounter::with_opts(Opts::new(name, help).namespace(NAMESPACE))
+            .expect("invalid metric definition");
+        Self::register(registry, Box::new(counter.clone()));
+        counter
+    }
+
+    fn counter_vec(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+        let counter = IntCounterVec::new(Opts::new(name, help).namespace(NAMESPACE), labels)
+            .expect("invalid metric definition");
+        Self::register(registry, Box::new(counter.clone()));
+        counter
+    }
+
+    fn histogram(registry: &Registry, name: &str, help: &str, buckets: Vec<f64>) -> Histogram {
+        let histogram = Histogram::with_opts(HistogramOpts::new(name, help).namespace(NAMESPACE).buckets(buckets))
+            .expect("invalid metric definition");
+        Self::register(registry, Box::new(histogram.clone()));
+        histogram
+    }
+
+    fn histogram_vec(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> HistogramVec {
+        let histogram = HistogramVec::new(HistogramOpts::new(name, help).namespace(NAMESPACE), labels)
+            .expect("invalid metric definition");
+        Self::register(registry, Box::new(histogram.clone()));
+        histogram
+    }
+
+    fn register(registry: &Registry, collector: Box<dyn prometheus::core::Collector>) {
+        if let Err(e) = registry.register(collector) {
+            eprintln!("ERROR: Failed to register metric: {}", e);
+        }
+    }
+
+    /// Everything gathered so far, in Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            eprintln!("ERROR: Failed to encode metrics: {}", e);
+        }
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+
+    /// Count a success or failure under a `result` label
+    pub fn record(counter: &IntCounterVec, ok: bool) {
+        counter.with_label_values(&[if ok { "succeeded" } else { "failed" }]).inc();
+    }
+}
+
+/// Middleware counting REST requests and their latency by route pattern
+///
+/// The pattern (`/api/updates/{id}`) rather than the path keeps the number of series bounded.
+pub async fn track_requests<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<B>, Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+
+    let result = next.call(req).await;
+
+    let status = match &result {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let m = metrics();
+    m.http_requests.with_label_values(&[method.as_str(), route.as_str(), status.as_str()]).inc();
+    m.http_request_duration.with_label_values(&[method.as_str(), route.as_str()]).observe(started.elapsed().as_secs_f64());
+
+    result
+}
+
+/// Times an apply and counts it as failed unless `succeeded` is called first
+///
+/// The apply threads bail out in many places; dropping the timer on any of them is enough.
+pub struct ApplyTimer {
+    started: Instant,
+    finished: bool,
+}
+
+impl ApplyTimer {
+    pub fn start() -> ApplyTimer {
+        ApplyTimer { started: Instant::now(), finished: false }
+    }
+
+    pub fn succeeded(mut self) {
+        self.finished = true;
+        let m = metrics();
+        m.apply_duration.observe(self.started.elapsed().as_secs_f64());
+        Metrics::record(&m.applies, true);
+    }
+}
+
+impl Drop for ApplyTimer {
+    fn drop(&mut self) {
+        if !self.finished {
+            Metrics::record(&metrics().applies, false);
+        }
+    }
+}
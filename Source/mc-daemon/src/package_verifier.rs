@@ -0,0 +1,105 @@
+use std::{fs::{self, File}, io::Read, path::{Path, PathBuf}};
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine};
+use ed25519_dalek::pkcs8::DecodePublicKey;
+use sha2::{Digest, Sha256};
+use rsa::signature::Verifier;
+
+/// A public key allowed to sign update packages
+enum TrustedKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    RsaPss(rsa::pss::VerifyingKey<Sha256>),
+}
+
+/// Checks detached MPAK signatures against the keys listed in `trusted_signing_keys`
+///
+/// A signature covers the SHA-256 digest of the package: Ed25519 signs the 32 digest bytes
+/// directly, RSA-PSS (SHA-256) signs them as its message.  Signatures may be stored either
+/// raw or base64 encoded.
+pub struct PackageVerifier {
+    keys: Vec<TrustedKey>,
+}
+
+impl PackageVerifier {
+    /// Load the trusted public keys (PEM, SubjectPublicKeyInfo) from the given files
+    pub fn load(key_paths: &[PathBuf]) -> Result<PackageVerifier> {
+        let mut keys = Vec::with_capacity(key_paths.len());
+
+        for path in key_paths {
+            let pem = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read signing key: {:?}", path))?;
+
+            let key = if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(&pem) {
+                TrustedKey::Ed25519(key)
+            } else if let Ok(key) = rsa::RsaPublicKey::from_public_key_pem(&pem) {
+                TrustedKey::RsaPss(rsa::pss::VerifyingKey::<Sha256>::new(key))
+            } else {
+                anyhow::bail!("Signing key {:?} is not an Ed25519 or RSA public key", path);
+            };
+
+            keys.push(key);
+        }
+
+        if keys.is_empty() {
+            anyhow::bail!("Signed updates are required but no trusted_signing_keys are configured");
+        }
+
+        Ok(PackageVerifier { keys })
+    }
+
+    /// Verify a package file against a detached signature
+    pub fn verify_file(&self, package_path: &Path, signature: &[u8]) -> Result<()> {
+        let digest = Self::sha256_file(package_path)?;
+        self.verify_digest(&digest, signature)
+    }
+
+    /// Verify a package digest against a detached signature, accepting any trusted key
+    pub fn verify_digest(&self, digest: &[u8], signature: &[u8]) -> Result<()> {
+        let signature = Self::decode_signature(signature);
+
+        for key in &self.keys {
+            let verified = match key {
+                TrustedKey::Ed25519(k) => ed25519_dalek::Signature::from_slice(&signature)
+                    .map(|s| k.verify_strict(digest, &s).is_ok())
+                    .unwrap_or(false),
+                TrustedKey::RsaPss(k) => rsa::pss::Signature::try_from(signature.as_slice())
+                    .map(|s| k.verify(digest, &s).is_ok())
+                    .unwrap_or(false),
+            };
+
+            if verified {
+                return Ok(());
+            }
+        }
+
+        anyhow::bail!("Package signature does not match any trusted signing key")
+    }
+
+    pub fn sha256_file(path: &Path) -> Result<Vec<u8>> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open package: {:?}", path))?;
+
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let count = file.read(&mut buffer)
+                .with_context(|| format!("Failed to read package: {:?}", path))?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+        }
+
+        Ok(hasher.finalize().to_vec())
+    }
+
+    /// Signatures are usually shipped as base64 text; fall back to treating them as raw bytes
+    fn decode_signature(signature: &[u8]) -> Vec<u8> {
+        match std::str::from_utf8(signature) {
+            Ok(text) => general_purpose::STANDARD
+                .decode(text.trim())
+                .unwrap_or_else(|_| signature.to_vec()),
+            Err(_) => signature.to_vec()
+        }
+    }
+}
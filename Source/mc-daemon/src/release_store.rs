@@ -0,0 +1,312 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+
+use crate::{cloud_settings::CloudSettings, update_store::UpdateStore};
+
+/// An app version kept in the release directory
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Release {
+    pub id: String,
+    #[serde(rename = "installedAt")]
+    pub installed_at: u64,
+    #[serde(default)]
+    pub active: bool,
+}
+
+/// Versioned app installs: one `releases/<id>/` directory per version plus a `current` symlink
+///
+/// The app directory itself is replaced by a symlink to `releases/current`, so switching
+/// versions is a single atomic rename of `current`.  Install order is kept in `releases.json`.
+pub struct ReleaseStore {
+    root: PathBuf,
+    keep: u32,
+}
+
+impl ReleaseStore {
+    const CURRENT_LINK_NAME: &'static str = "current";
+    const TEMP_LINK_NAME: &'static str = ".current.tmp";
+    const MANIFEST_FILE_NAME: &'static str = "releases.json";
+    const INITIAL_RELEASE_ID: &'static str = "initial";
+
+    pub fn new(settings: &CloudSettings) -> ReleaseStore {
+        ReleaseStore {
+            root: settings.releases_path.clone(),
+            keep: settings.keep_releases,
+        }
+    }
+
+    /// Install a staged app as a new release, make it current and prune old releases
+    ///
+    /// Returns the id the release was stored under (`id`, made unique if it is already taken).
+    pub fn install(&self, app_dir: &Path, staging: &Path, id: &str) -> Result<String, String> {
+        if let Err(e) = fs::create_dir_all(&self.root) {
+            return Err(format!("Failed to create release directory {:?}: {}", self.root, e));
+        }
+
+        self.adopt_app_dir(app_dir)?;
+
+        let mut releases = self.load_manifest();
+        let release_id = self.unique_id(&releases, id);
+        let release_dir = self.root.join(&release_id);
+
+        println!("Installing release '{}' at {:?}", release_id, release_dir);
+        Self::move_dir(staging, &release_dir)?;
+
+        releases.push(Release {
+            id: release_id.clone(),
+            installed_at: Self::now(),
+            active: false,
+        });
+        self.save_manifest(&releases)?;
+
+        self.set_current(&release_id)?;
+        self.prune();
+
+        Ok(release_id)
+    }
+
+    /// Make an installed release current
+    pub fn activate(&self, id: &str) -> Result<(), String> {
+        if !self.load_manifest().iter().any(|r| r.id == id) {
+            return Err(format!("Release '{}' not found", id));
+        }
+
+        self.set_current(id)
+    }
+
+    /// Switch back to the release installed before the current one, discarding the current one
+    ///
+    /// Returns the id of the release that is now current.
+    pub fn roll_back(&self) -> Result<String, String> {
+        let current = self.current()
+            .ok_or_else(|| "No release is currently active".to_string())?;
+        let previous = self.previous()
+            .ok_or_else(|| format!("No release older than '{}' to roll back to", current))?;
+
+        self.set_current(&previous)?;
+
+        let mut releases = self.load_manifest();
+        releases.retain(|r| r.id != current);
+        self.save_manifest(&releases)?;
+
+        if let Err(e) = fs::remove_dir_all(self.root.join(&current)) {
+            eprintln!("WARNING: Failed to remove release '{}': {}", current, e);
+        }
+
+        Ok(previous)
+    }
+
+    /// The id of the active release
+    pub fn current(&self) -> Option<String> {
+        fs::read_link(self.root.join(Self::CURRENT_LINK_NAME))
+            .ok()
+            .and_then(|target| target.file_name().map(|n| n.to_string_lossy().to_string()))
+    }
+
+    /// The release installed just before the active one
+    pub fn previous(&self) -> Option<String> {
+        let current = self.current()?;
+        let releases = self.load_manifest();
+        let position = releases.iter().position(|r| r.id == current)?;
+        releases[..position].last().map(|r| r.id.clone())
+    }
+
+    /// All retained releases, oldest first
+    pub fn list(&self) -> Vec<Release> {
+        let current = self.current();
+        self.load_manifest()
+            .into_iter()
+            .map(|mut r| {
+                r.active = current.as_deref() == Some(r.id.as_str());
+                r
+            })
+            .collect()
+    }
+
+    /// Point `current` at a release by renaming a new link over the old one
+    fn set_current(&self, id: &str) -> Result<(), String> {
+        let link = self.root.join(Self::CURRENT_LINK_NAME);
+        let temp_link = self.root.join(Self::TEMP_LINK_NAME);
+
+        let _ = fs::remove_file(&temp_link);
+
+        // relative target, so the release tree still works if it is moved as a whole
+        if let Err(e) = symlink(id, &temp_link) {
+            return Err(format!("Failed to create link to release '{}': {}", id, e));
+        }
+        if let Err(e) = fs::rename(&temp_link, &link) {
+            let _ = fs::remove_file(&temp_link);
+            return Err(format!("Failed to switch current release to '{}': {}", id, e));
+        }
+
+        println!("Release '{}' is now current", id);
+        Ok(())
+    }
+
+    /// Turn the app directory into a symlink to `current`
+    ///
+    /// The first time round an existing app directory is moved in as the initial release.
+    fn adopt_app_dir(&self, app_dir: &Path) -> Result<(), String> {
+        let current_link = self.root.join(Self::CURRENT_LINK_NAME);
+
+        match fs::symlink_metadata(app_dir) {
+            Ok(m) if m.file_type().is_symlink() => {
+                return match fs::read_link(app_dir) {
+                    Ok(target) if target == current_link => Ok(()),
+                    _ => Err(format!("App directory {:?} is a symlink not managed by the release store", app_dir)),
+                };
+            },
+            Ok(m) if m.is_dir() => {
+                let initial_dir = self.root.join(Self::INITIAL_RELEASE_ID);
+                if initial_dir.exists() {
+                    return Err(format!("Cannot adopt {:?}: {:?} already exists", app_dir, initial_dir));
+                }
+
+                println!("Moving existing app directory {:?} into the release store", app_dir);
+                Self::move_dir(app_dir, &initial_dir)?;
+
+                let mut releases = self.load_manifest();
+                releases.push(Release {
+                    id: Self::INITIAL_RELEASE_ID.to_string(),
+                    installed_at: Self::now(),
+                    active: false,
+                });
+                self.save_manifest(&releases)?;
+                self.set_current(Self::INITIAL_RELEASE_ID)?;
+            },
+            Ok(_) => {
+                return Err(format!("App directory {:?} is not a directory", app_dir));
+            },
+            Err(_) => {
+                // nothing installed yet
+                if let Some(parent) = app_dir.parent()
+                    && let Err(e) = fs::create_dir_all(parent) {
+                    return Err(format!("Failed to create directory {:?}: {}", parent, e));
+                }
+            }
+        }
+
+        if let Err(e) = symlink(&current_link, app_dir) {
+            return Err(format!("Failed to link {:?} to {:?}: {}", app_dir, current_link, e));
+        }
+
+        Ok(())
+    }
+
+    /// Remove the oldest releases beyond the configured count, never touching the active one
+    fn prune(&self) {
+        let current = self.current();
+        let mut releases = self.load_manifest();
+
+        let mut older = releases.iter()
+            .filter(|r| current.as_deref() != Some(r.id.as_str()))
+            .count();
+
+        let mut removed = Vec::new();
+        for release in &releases {
+            if older <= self.keep as usize {
+                break;
+            }
+            if current.as_deref() == Some(release.id.as_str()) {
+                continue;
+            }
+
+            println!("Removing old release '{}'", release.id);
+            if let Err(e) = fs::remove_dir_all(self.root.join(&release.id)) {
+                eprintln!("WARNING: Failed to remove release '{}': {}", release.id, e);
+                continue;
+            }
+            removed.push(release.id.clone());
+            older -= 1;
+        }
+
+        if !removed.is_empty() {
+            releases.retain(|r| !removed.contains(&r.id));
+            if let Err(e) = self.save_manifest(&releases) {
+                eprintln!("WARNING: {}", e);
+            }
+        }
+    }
+
+    /// Release ids double as directory names; add a suffix if the id is already taken
+    fn unique_id(&self, releases: &[Release], id: &str) -> String {
+        let taken = |candidate: &str| {
+            candidate == Self::CURRENT_LINK_NAME
+                || releases.iter().any(|r| r.id == candidate)
+                || fs::symlink_metadata(self.root.join(candidate)).is_ok()
+        };
+
+        if !taken(id) {
+            return id.to_string();
+        }
+
+        (2..)
+            .map(|n| format!("{}-{}", id, n))
+            .find(|candidate| !taken(candidate))
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    /// Rename a directory, copying instead when it has to cross filesystems
+    fn move_dir(source: &Path, dest: &Path) -> Result<(), String> {
+        match fs::rename(source, dest) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+                UpdateStore::copy_tree(source, dest, None)?;
+                if let Err(e) = fs::remove_dir_all(source) {
+                    eprintln!("WARNING: Failed to remove {:?} after copying it: {}", source, e);
+                }
+                Ok(())
+            },
+            Err(e) => Err(format!("Failed to move {:?} to {:?}: {}", source, dest, e)),
+        }
+    }
+
+    /// Manifest entries whose directory still exists, oldest first
+    fn load_manifest(&self) -> Vec<Release> {
+        let path = self.root.join(Self::MANIFEST_FILE_NAME);
+
+        let releases: Vec<Release> = match File::open(&path) {
+            Ok(file) => match serde_json::from_reader(BufReader::new(file)) {
+                Ok(releases) => releases,
+                Err(e) => {
+                    eprintln!("WARNING: Failed to parse {:?}: {}", path, e);
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+
+        releases.into_iter()
+            .filter(|r| self.root.join(&r.id).is_dir())
+            .collect()
+    }
+
+    fn save_manifest(&self, releases: &[Release]) -> Result<(), String> {
+        let path = self.root.join(Self::MANIFEST_FILE_NAME);
+
+        let json = serde_json::to_string_pretty(releases)
+            .map_err(|e| format!("Failed to serialize release list: {}", e))?;
+
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(json.as_bytes()))
+            .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,401 @@
+use std::{collections::HashMap, fmt, fs, path::Path, sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+use actix_web::{body::{EitherBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, http::{Method, header::{self, HeaderValue}}, middleware::Next, web, Error, HttpMessage, HttpRequest, ResponseError};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{cloud_settings::CloudSettings, rest_error::ApiError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const BEARER_PREFIX: &str = "Bearer ";
+const HMAC_PREFIX: &str = "HMAC-SHA256 ";
+/// How far an HMAC request's timestamp may be from the daemon's clock; nonces are remembered this long
+const HMAC_MAX_SKEW_SECONDS: u64 = 300;
+const HMAC_MAX_NONCE_LENGTH: usize = 64;
+
+/// What a REST API token is allowed to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Daemon info, status, updates, releases and events
+    Read,
+    /// Downloading updates and clearing the update store
+    Download,
+    /// Applying, confirming and rolling back updates and switching releases
+    Apply,
+    /// Listing files under meadow_root
+    Files,
+    /// Registering the device (POST /api/provision) and cloud reconnects
+    Admin,
+}
+
+impl Scope {
+    const ALL: [Scope; 5] = [Scope::Read, Scope::Download, Scope::Apply, Scope::Files, Scope::Admin];
+
+    fn parse(val: &str) -> Option<Scope> {
+        match val.to_lowercase().as_str() {
+            "read" => Some(Scope::Read),
+            "download" => Some(Scope::Download),
+            "apply" => Some(Scope::Apply),
+            "files" => Some(Scope::Files),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::Read => "read",
+            Scope::Download => "download",
+            Scope::Apply => "apply",
+            Scope::Files => "files",
+            Scope::Admin => "admin",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A client allowed to call the REST API
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiToken {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(skip_serializing)]
+    secret: String,
+}
+
+impl ApiToken {
+    pub fn new(name: &str, scopes: Vec<Scope>, secret: &str) -> ApiToken {
+        ApiToken { name: name.to_string(), scopes, secret: secret.to_string() }
+    }
+
+    /// Parse `<name> <scope>[,<scope>...] <secret>`; `all` grants every scope
+    pub fn parse(val: &str) -> Result<ApiToken, String> {
+        let parts: Vec<&str> = val.split_whitespace().collect();
+        let [name, scopes, secret] = parts[..] else {
+            return Err("expected '<name> <scopes> <secret>'".to_string());
+        };
+
+        let scopes = if scopes.eq_ignore_ascii_case("all") {
+            Scope::ALL.to_vec()
+        } else {
+            scopes.split(',')
+                .map(|s| Scope::parse(s.trim()).ok_or_else(|| format!("unknown scope '{}'", s)))
+                .collect::<Result<Vec<Scope>, String>>()?
+        };
+
+        Ok(ApiToken::new(name, scopes, secret))
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// The token a request was authenticated with, stored in the request extensions
+#[derive(Clone, Debug)]
+pub struct ApiCaller {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// REST API authentication: bearer tokens or HMAC-signed requests, each with scoped permissions
+///
+/// HMAC requests send `Authorization: HMAC-SHA256 <name>:<unix time>:<nonce>:<signature>`, where
+/// the signature is the hex HMAC-SHA256, keyed with the token's secret, of
+/// `<METHOD>\n<path and query>\n<unix time>\n<nonce>\n<hex SHA-256 of the body>`.  A nonce can
+/// only be used once per token while its timestamp is within the allowed skew, so a captured
+/// request can't be replayed.
+#[derive(Clone, Default)]
+pub struct RestAuth {
+    enabled: bool,
+    tokens: Vec<ApiToken>,
+    /// (token name, nonce) -> request time; shared by the clones each server worker gets
+    seen_nonces: Arc<Mutex<HashMap<(String, String), u64>>>,
+}
+
+impl RestAuth {
+    pub fn new(enabled: bool, tokens: Vec<ApiToken>) -> RestAuth {
+        RestAuth { enabled, tokens, seen_nonces: Arc::default() }
+    }
+
+    /// Tokens from meadow.conf plus any in `rest_api_token_file`
+    pub fn from_settings(settings: &CloudSettings) -> RestAuth {
+        let mut tokens = settings.rest_api_tokens.clone();
+        if let Some(path) = &settings.rest_api_token_file {
+            tokens.extend(Self::read_token_file(path));
+        }
+
+        if settings.rest_api_auth && tokens.is_empty() {
+            println!("WARNING: rest_api_auth is enabled but no tokens are configured; every REST call will be refused");
+        }
+
+        RestAuth::new(settings.rest_api_auth, tokens)
+    }
+
+    /// One token per line as `<name> <scopes> <secret>`; blank lines and '#' comments are skipped
+    fn read_token_file(path: &Path) -> Vec<ApiToken> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("ERROR: Failed to read REST API token file {:?}: {}", path, e);
+                return Vec::new();
+            }
+        };
+
+        contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| ApiToken::parse(line)
+                .map_err(|e| println!("WARNING: Skipping invalid token in {:?}: {}", path, e))
+                .ok())
+            .collect()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Work out which token signed or carries this request
+    pub fn authenticate(&self, method: &Method, path_and_query: &str, authorization: Option<&str>, body: &[u8]) -> Result<&ApiToken, String> {
+        let header = authorization.ok_or("Missing Authorization header")?;
+
+        if let Some(secret) = header.strip_prefix(BEARER_PREFIX) {
+            let secret = secret.trim().as_bytes();
+            return self.tokens.iter()
+                .find(|t| bool::from(t.secret.as_bytes().ct_eq(secret)))
+                .ok_or_else(|| "Invalid token".to_string());
+        }
+
+        if let Some(credentials) = header.strip_prefix(HMAC_PREFIX) {
+            let mut parts = credentials.trim().splitn(4, ':');
+            let (Some(name), Some(timestamp), Some(nonce), Some(signature)) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+                return Err("Expected 'HMAC-SHA256 <name>:<timestamp>:<nonce>:<signature>'".to_string());
+            };
+            if nonce.is_empty() || nonce.len() > HMAC_MAX_NONCE_LENGTH
+                || !nonce.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+                return Err(format!("HMAC nonce must be 1-{} letters, digits, '-' or '_'", HMAC_MAX_NONCE_LENGTH));
+            }
+
+            let timestamp: u64 = timestamp.parse().map_err(|_| "Invalid HMAC timestamp")?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            if now.abs_diff(timestamp) > HMAC_MAX_SKEW_SECONDS {
+                return Err("HMAC timestamp is too far from the daemon's clock".to_string());
+            }
+
+            let token = self.tokens.iter()
+                .find(|t| t.name == name)
+                .ok_or("Invalid token")?;
+            let signature = decode_hex(signature).ok_or("Invalid HMAC signature")?;
+            let mut mac = HmacSha256::new_from_slice(token.secret.as_bytes())
+                .map_err(|e| e.to_string())?;
+            mac.update(Self::canonical_request(method, path_and_query, timestamp, nonce, body).as_bytes());
+            mac.verify_slice(&signature).map_err(|_| "Invalid HMAC signature")?;
+
+            // only remembered once the signature checks out, so nobody can burn another client's nonces
+            self.use_nonce(&token.name, nonce, timestamp, now)?;
+            return Ok(token);
+        }
+
+        Err("Unsupported Authorization scheme".to_string())
+    }
+
+    /// Record a nonce, refusing one already seen; entries older than the skew window are dropped
+    /// since their timestamps would be refused anyway
+    fn use_nonce(&self, name: &str, nonce: &str, timestamp: u64, now: u64) -> Result<(), String> {
+        let mut seen = self.seen_nonces.lock()
+            .map_err(|e| format!("Failed to lock HMAC nonces: {}", e))?;
+        seen.retain(|_, at| now.abs_diff(*at) <= HMAC_MAX_SKEW_SECONDS);
+
+        if seen.insert((name.to_string(), nonce.to_string()), timestamp).is_some() {
+            return Err("HMAC nonce has already been used".to_string());
+        }
+        Ok(())
+    }
+
+    /// The string an HMAC request signs
+    pub fn canonical_request(method: &Method, path_and_query: &str, timestamp: u64, nonce: &str, body: &[u8]) -> String {
+        format!("{}\n{}\n{}\n{}\n{}", method, path_and_query, timestamp, nonce, encode_hex(&Sha256::digest(body)))
+    }
+
+    /// Sign a request the way a client should; `nonce` must be new for every request
+    pub fn sign(secret: &str, method: &Method, path_and_query: &str, timestamp: u64, nonce: &str, body: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(Self::canonical_request(method, path_and_query, timestamp, nonce, body).as_bytes());
+        encode_hex(&mac.finalize().into_bytes())
+    }
+
+    /// Check a handler-level scope, for permissions that depend on the request body
+    ///
+    /// Requests always pass when authentication is disabled.
+    pub fn authorize(req: &HttpRequest, scope: Scope) -> Result<(), String> {
+        match req.extensions().get::<ApiCaller>() {
+            Some(caller) if !caller.scopes.contains(&scope) => Err(missing_scope(&caller.name, scope)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Middleware rejecting requests without a valid token (401)
+///
+/// The token is left in the request extensions as an [`ApiCaller`]; each route checks its own
+/// scope with [`require_read`] and friends once the router has matched it.
+pub async fn require_token<B: MessageBody>(mut req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let auth = match req.app_data::<web::Data<RestAuth>>() {
+        Some(auth) if auth.is_enabled() => auth.clone(),
+        _ => return next.call(req).await.map(ServiceResponse::map_into_left_body),
+    };
+
+    let authorization = req.headers().get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .map(str::to_string);
+
+    // HMAC signatures cover the body, so read it and put it back for the handler
+    let body = if authorization.as_deref().is_some_and(|h| h.starts_with(HMAC_PREFIX)) {
+        let body = req.extract::<web::Bytes>().await?;
+        req.set_payload(body.clone().into());
+        body
+    } else {
+        web::Bytes::new()
+    };
+
+    let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or(req.path()).to_string();
+    let token = match auth.authenticate(req.method(), &path_and_query, authorization.as_deref(), &body) {
+        Ok(token) => token,
+        Err(msg) => {
+            eprintln!("WARNING: REST call refused: {}", msg);
+            let mut response = ApiError::Unauthorized(msg).error_response();
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer realm=\"meadow\""));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    };
+
+    req.extensions_mut().insert(ApiCaller { name: token.name.clone(), scopes: token.scopes.clone() });
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// Route middleware: the token needs the `read` scope
+pub async fn require_read<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    require_scope(Scope::Read, req, next).await
+}
+
+/// Route middleware: the token needs the `download` scope
+pub async fn require_download<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    require_scope(Scope::Download, req, next).await
+}
+
+/// Route middleware: the token needs the `apply` scope
+pub async fn require_apply<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    require_scope(Scope::Apply, req, next).await
+}
+
+/// Route middleware: the token needs the `files` scope
+pub async fn require_files<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    require_scope(Scope::Files, req, next).await
+}
+
+/// Route middleware: the token needs the `admin` scope
+pub async fn require_admin<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    require_scope(Scope::Admin, req, next).await
+}
+
+/// Refuse (403) a request whose token lacks `scope`
+///
+/// This runs on the route the router matched, so the scope can't be dodged by spelling the
+/// path differently.  Requests always pass when authentication is disabled.
+async fn require_scope<B: MessageBody>(scope: Scope, req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let refusal = match req.extensions().get::<ApiCaller>() {
+        Some(caller) if !caller.scopes.contains(&scope) => Some(missing_scope(&caller.name, scope)),
+        _ => None,
+    };
+
+    if let Some(msg) = refusal {
+        eprintln!("WARNING: REST call refused: {}", msg);
+        let response = ApiError::Forbidden(msg).error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// The user and group of the process on the other end of a Unix socket connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: Option<i32>,
+}
+
+/// Connection hook recording `SO_PEERCRED` for Unix socket connections; TCP connections get none
+#[cfg(unix)]
+pub fn record_peer_credentials(connection: &dyn std::any::Any, data: &mut actix_web::dev::Extensions) {
+    if let Some(stream) = connection.downcast_ref::<tokio::net::UnixStream>() {
+        match stream.peer_cred() {
+            Ok(cred) => {
+                data.insert(PeerCredentials { uid: cred.uid(), gid: cred.gid(), pid: cred.pid() });
+            },
+            Err(e) => eprintln!("ERROR: Failed to read Unix socket peer credentials: {}", e),
+        }
+    }
+}
+
+/// Which local users may call mutating endpoints over the Unix socket
+///
+/// With no UIDs or GIDs configured, anyone who can open the socket may.
+#[derive(Clone, Debug, Default)]
+pub struct PeerPolicy {
+    uids: Vec<u32>,
+    gids: Vec<u32>,
+}
+
+impl PeerPolicy {
+    pub fn new(uids: Vec<u32>, gids: Vec<u32>) -> PeerPolicy {
+        PeerPolicy { uids, gids }
+    }
+
+    pub fn allows(&self, peer: &PeerCredentials) -> bool {
+        (self.uids.is_empty() && self.gids.is_empty())
+            || self.uids.contains(&peer.uid)
+            || self.gids.contains(&peer.gid)
+    }
+}
+
+/// Middleware refusing (403) anything but GET from Unix socket peers outside the [`PeerPolicy`]
+pub async fn require_peer<B: MessageBody>(req: ServiceRequest, next: Next<B>) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let peer = req.conn_data::<PeerCredentials>().copied();
+    let policy = req.app_data::<web::Data<PeerPolicy>>();
+
+    if let (Some(peer), Some(policy)) = (peer, policy)
+        && req.method() != Method::GET
+        && !policy.allows(&peer) {
+        eprintln!("WARNING: REST call refused: uid {} / gid {} is not in the peer policy", peer.uid, peer.gid);
+        let response = ApiError::Forbidden(format!("uid {} / gid {} is not allowed to call {} {}", peer.uid, peer.gid, req.method(), req.path()))
+            .error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+fn missing_scope(name: &str, scope: Scope) -> String {
+    format!("Token '{}' does not have the '{}' scope", name, scope)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
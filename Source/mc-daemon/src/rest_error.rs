@@ -0,0 +1,119 @@
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use serde::Serialize;
+use serde_json::{Value, json};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::update_error::UpdateError;
+
+/// Body of every REST API error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Stable, machine-readable reason, e.g. `update_not_downloaded` or `bad_package`
+    pub code: String,
+    pub message: String,
+    /// Extra fields for some codes, e.g. the update `id` or the `pid`
+    pub details: Option<Value>,
+}
+
+/// Why a REST call failed; each variant maps to one HTTP status and error code
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    Update(#[from] UpdateError),
+    /// The request body or parameters don't make sense
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("Unknown action '{0}'")]
+    UnknownAction(String),
+    #[error("Process {pid} not found: {reason}")]
+    PidNotFound { pid: i32, reason: String },
+    #[error("{0}")]
+    InvalidPath(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("No device key available at {0}")]
+    NoDeviceKey(String),
+    #[error("The MQTT listener is disabled")]
+    ListenerDisabled,
+    #[error("{0}")]
+    ReconnectRejected(String),
+    /// Meadow.Cloud couldn't be reached or turned the request down
+    #[error("{0}")]
+    Upstream(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Update(e) => e.code(),
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::UnknownAction(_) => "unknown_action",
+            ApiError::PidNotFound { .. } => "pid_not_found",
+            ApiError::InvalidPath(_) => "invalid_path",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NoDeviceKey(_) => "no_device_key",
+            ApiError::ListenerDisabled => "listener_disabled",
+            ApiError::ReconnectRejected(_) => "reconnect_rejected",
+            ApiError::Upstream(_) => "upstream_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn details(&self) -> Option<Value> {
+        match self {
+            ApiError::Update(UpdateError::NotFound(id))
+            | ApiError::Update(UpdateError::NotDownloaded(id))
+            | ApiError::Update(UpdateError::DownloadInProgress(id))
+            | ApiError::Update(UpdateError::ReleaseNotFound(id)) => Some(json!({ "id": id })),
+            ApiError::UnknownAction(action) => Some(json!({ "action": action, "expected": ["download", "apply"] })),
+            ApiError::PidNotFound { pid, .. } => Some(json!({ "pid": pid })),
+            ApiError::NoDeviceKey(path) => Some(json!({ "path": path })),
+            _ => None,
+        }
+    }
+
+    /// A poisoned lock on shared state
+    pub fn lock_failed(what: &str, e: impl std::fmt::Display) -> ApiError {
+        eprintln!("ERROR: Failed to lock {}: {}", what, e);
+        ApiError::Internal(format!("Failed to lock {}", what))
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Update(e) => match e {
+                UpdateError::NotFound(_) | UpdateError::ReleaseNotFound(_) => StatusCode::NOT_FOUND,
+                UpdateError::NotDownloaded(_)
+                | UpdateError::DownloadInProgress(_)
+                | UpdateError::NotExtracted(_)
+                | UpdateError::NoPreviousVersion(_)
+                | UpdateError::ReleasesDisabled => StatusCode::CONFLICT,
+                UpdateError::BadPackage(_) | UpdateError::BadSignature(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                UpdateError::DownloadFailed(_) => StatusCode::BAD_GATEWAY,
+                UpdateError::Io(_) | UpdateError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            ApiError::InvalidRequest(_) | ApiError::UnknownAction(_) | ApiError::InvalidPath(_) => StatusCode::BAD_REQUEST,
+            ApiError::PidNotFound { .. } => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NoDeviceKey(_) | ApiError::ListenerDisabled | ApiError::ReconnectRejected(_) => StatusCode::CONFLICT,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            code: self.code().to_string(),
+            message: self.to_string(),
+            details: self.details(),
+        })
+    }
+}
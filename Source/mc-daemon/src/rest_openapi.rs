@@ -0,0 +1,65 @@
+//! OpenAPI description of the REST API, served at `/api/openapi.json`
+//!
+//! Each handler in `rest_server` carries its own `#[utoipa::path]`; this collects them along with
+//! the schemas they use.
+//!
+//! Every error comes back as an `ErrorResponse`; besides the statuses listed per route, any
+//! call can get 401/403 when rest_api_auth is on and 500 `internal_error`.
+
+use utoipa::{Modify, OpenApi, openapi::security::{Http, HttpAuthScheme, SecurityScheme}};
+
+use crate::{
+    cloud_settings::{HealthCheckMode, KeyWrapAlgorithm},
+    events::DaemonEvent,
+    health::{ComponentHealth, HealthResponse, HealthStatus, ReadinessComponents},
+    release_store::Release,
+    rest_error::ErrorResponse,
+    rest_server::{self, ActionResult, ApplyAction, ConfigResponse, DeviceInfo, FileInfo, FileListResponse, ProvisionInfo, ProvisionRequest, ProvisionResult, RestartAction, ServiceInfo, StatusResponse, UpdateAction, UpdateInfo},
+    update_descriptor::{UpdateDescriptor, UpdateStatus},
+    update_service::{ServiceStatus, UpdateState},
+    update_store::DownloadProgress,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Meadow.Daemon REST API", description = "Update management for Meadow apps on Linux"),
+    paths(
+        rest_server::get_daemon_info, rest_server::get_service_status, rest_server::reconnect_cloud,
+        rest_server::get_provision_info, rest_server::provision_device, rest_server::get_updates,
+        rest_server::confirm_update, rest_server::get_update, rest_server::update_action,
+        rest_server::clear_update_store, rest_server::apply_extracted, rest_server::rollback,
+        rest_server::get_releases, rest_server::switch_release, rest_server::stream_events,
+        rest_server::list_files, rest_server::list_files_in, rest_server::get_openapi,
+        rest_server::get_metrics, rest_server::get_health, rest_server::get_readiness,
+    ),
+    components(schemas(
+        ServiceInfo, DeviceInfo, ConfigResponse, StatusResponse, ServiceStatus, UpdateState,
+        ProvisionInfo, ProvisionRequest, ProvisionResult, UpdateInfo, UpdateDescriptor, UpdateStatus,
+        DownloadProgress, UpdateAction, ApplyAction, RestartAction, Release, FileListResponse, FileInfo,
+        DaemonEvent, KeyWrapAlgorithm, HealthCheckMode, ActionResult, ErrorResponse, HealthResponse, HealthStatus,
+        ComponentHealth, ReadinessComponents,
+    )),
+    modifiers(&TokenAuth),
+    // tokens are only checked when rest_api_auth is on
+    security((), ("bearer" = [])),
+)]
+pub struct ApiDoc;
+
+struct TokenAuth;
+
+impl Modify for TokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+impl ApiDoc {
+    pub fn json() -> String {
+        ApiDoc::openapi().to_pretty_json()
+            .unwrap_or_else(|e| {
+                eprintln!("ERROR: Failed to serialize the OpenAPI document: {}", e);
+                "{}".to_string()
+            })
+    }
+}
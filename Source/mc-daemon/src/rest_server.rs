@@ -1,10 +1,13 @@
-use std::{time::{SystemTime, UNIX_EPOCH}, sync::{Mutex, Arc}, fs::{self}, path::PathBuf};
-use actix_web::{App, Error, HttpResponse, HttpServer, web, Responder};
+use std::{time::{Duration, SystemTime, UNIX_EPOCH}, sync::{Mutex, Arc}, fs::{self}, path::{Path, PathBuf}};
+use actix_web::{App, Error, HttpRequest, HttpResponse, HttpServer, guard, middleware, web};
+use futures_util::stream;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::ToSchema;
 
-use crate::{crypto::Crypto, update_descriptor::UpdateDescriptor, update_store::UpdateStore};
+use crate::{cloud_settings::{HealthCheckMode, KeyWrapAlgorithm}, crypto::Crypto, device_id::DeviceId, events::DaemonEvent, health::HealthResponse, metrics::{self, metrics}, rest_auth::{self, PeerPolicy, RestAuth, Scope}, rest_error::{ApiError, ErrorResponse}, release_store::Release, rest_openapi::ApiDoc, rest_tls, update_descriptor::UpdateDescriptor, update_error::UpdateError, update_service::ServiceStatus, update_store::{DownloadProgress, UpdateStore}};
 
-const PORT: &str = "5000";
+const EVENT_KEEPALIVE_SECONDS: u64 = 15;
 
 /*
 #[derive(Serialize, Deserialize)]
@@ -15,8 +18,8 @@ struct File {
 }
 */
 
-#[derive(Serialize, Deserialize)]
-struct DeviceInfo {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct DeviceInfo {
     serial_number: String,
     device_name: String,
     platform: String,
@@ -26,8 +29,8 @@ struct DeviceInfo {
     machine: String
 }
 
-#[derive(Serialize, Deserialize)]
-struct ServiceInfo {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct ServiceInfo {
     service: String,
     up_time: u64,
     version: String,
@@ -37,38 +40,106 @@ struct ServiceInfo {
     config: ConfigResponse
 }
 
-#[derive(Serialize, Deserialize)]
-struct UpdateAction {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct UpdateAction {
     action: String,
     pid: Option<i32>,
     app_dir: Option<String>,
     command: Option<String>
 }
 
-#[derive(Serialize, Deserialize)]
-struct ApplyAction {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct ApplyAction {
     pid: Option<i32>,
     app_dir: Option<String>,
     executable: Option<String>,
     command: Option<String>
 }
 
-#[derive(Serialize, Deserialize)]
-struct FileInfo {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct RestartAction {
+    pid: Option<i32>,
+    app_dir: Option<String>,
+    executable: Option<String>,
+    command: Option<String>
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct UpdateInfo {
+    #[serde(flatten)]
+    descriptor: UpdateDescriptor,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<DownloadProgress>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct StatusResponse {
+    #[serde(rename = "listenerEnabled")]
+    listener_enabled: bool,
+    #[serde(flatten)]
+    service: ServiceStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ProvisionInfo {
+    #[serde(rename = "deviceId")]
+    device_id: String,
+    #[serde(rename = "publicKey")]
+    public_key: String,
+    #[serde(rename = "publicKeyOpenSsh")]
+    public_key_openssh: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ProvisionResult {
+    #[serde(rename = "deviceId")]
+    device_id: String,
+    #[serde(rename = "publicKey")]
+    public_key: String,
+}
+
+/// Body of a request that started something; the same shape as [`ErrorResponse`]
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ActionResult {
+    /// Stable, machine-readable outcome, e.g. `rollback_started`
+    code: String,
+    message: String,
+    /// Extra fields for some codes, e.g. the update or release `id`
+    details: Option<serde_json::Value>,
+}
+
+impl ActionResult {
+    fn new(code: &str, message: String, details: Option<serde_json::Value>) -> ActionResult {
+        ActionResult { code: code.to_string(), message, details }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct ProvisionRequest {
+    token: String,
+    #[serde(rename = "orgId")]
+    org_id: Option<String>,
+    #[serde(rename = "collectionId")]
+    collection_id: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct FileInfo {
     name: String,
     #[serde(rename = "isDirectory")]
     is_directory: bool,
     size: u64,
 }
 
-#[derive(Serialize, Deserialize)]
-struct FileListResponse {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct FileListResponse {
     path: String,
     files: Vec<FileInfo>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct ConfigResponse {
+#[derive(Serialize, Deserialize, ToSchema)]
+pub(crate) struct ConfigResponse {
     enabled: bool,
     enable_mqtt_listener: bool,
     meadow_root: String,
@@ -77,7 +148,21 @@ struct ConfigResponse {
     temp_extract_path: String,
     staging_path: String,
     rollback_path: String,
+    releases_path: String,
+    keep_releases: u32,
     rest_api_bind_address: String,
+    rest_api_enable_tcp: bool,
+    rest_api_port: u16,
+    rest_api_socket_path: Option<String>,
+    rest_api_socket_mode: String,
+    rest_api_allowed_uids: Vec<u32>,
+    rest_api_allowed_gids: Vec<u32>,
+    rest_api_auth: bool,
+    rest_api_token_file: Option<String>,
+    rest_api_tls_cert_path: Option<String>,
+    rest_api_tls_key_path: Option<String>,
+    rest_api_tls_client_ca_path: Option<String>,
+    ready_requires_cloud: bool,
     update_server_address: String,
     update_server_port: i32,
     use_authentication: bool,
@@ -85,22 +170,56 @@ struct ConfigResponse {
     connect_retry_seconds: u64,
     update_apply_timeout_seconds: u64,
     auth_max_retries: u32,
+    auth_retry_minutes: u64,
+    device_key_path: Option<String>,
+    key_wrap_algorithm: KeyWrapAlgorithm,
+    device_id_source: String,
     auto_download_updates: bool,
+    download_max_retries: u32,
+    require_signed_updates: bool,
+    trusted_signing_keys: Vec<String>,
     app_is_systemd_service: bool,
     app_service_name: Option<String>,
+    health_check_mode: HealthCheckMode,
+    health_check_seconds: u64,
+    health_check_url: Option<String>,
 }
 
-pub struct RestServer;
+/// Work out the app directory and executable from an explicit `app_dir` + `executable`,
+/// or from `/proc/{pid}/exe` when no directory is given
+fn resolve_app_location(
+    pid: Option<i32>,
+    app_dir: &Option<String>,
+    executable: &Option<String>
+) -> Result<(PathBuf, PathBuf), ApiError> {
+    match app_dir {
+        None => {
+            let pid = pid.ok_or_else(|| ApiError::InvalidRequest("Either pid or app_dir is required".to_string()))?;
+
+            // Auto-detect from /proc/{pid}/exe
+            let exe_path = fs::read_link(format!("/proc/{}/exe", pid))
+                .map_err(|e| ApiError::PidNotFound { pid, reason: format!("failed to determine application path: {}", e) })?;
+            let dir = exe_path.parent()
+                .ok_or_else(|| ApiError::InvalidRequest(format!("Failed to get directory from executable path: {:?}", exe_path)))?
+                .to_path_buf();
+            Ok((dir, exe_path))
+        },
+        Some(dir_str) => {
+            // Use provided app_dir as the application directory
+            let dir = PathBuf::from(dir_str.trim_end_matches('/'));
 
-fn trim_newline(s: &mut String) {
-    if s.ends_with('\n') {
-        s.pop();
-        if s.ends_with('\r') {
-            s.pop();
+            // Construct executable path from app_dir + executable
+            let executable = executable.as_ref()
+                .ok_or_else(|| ApiError::InvalidRequest("executable is required when app_dir is provided".to_string()))?;
+
+            let exe_path = dir.join(executable);
+            Ok((dir, exe_path))
         }
     }
 }
 
+pub struct RestServer;
+
 fn validate_and_resolve_path(
     root: &PathBuf,
     requested_path: Option<&str>
@@ -126,16 +245,9 @@ fn validate_and_resolve_path(
 }
 
 impl ServiceInfo {
-    pub fn new(settings: &crate::cloud_settings::CloudSettings) -> ServiceInfo {
+    pub fn new(settings: &crate::cloud_settings::CloudSettings, device_id: &DeviceId) -> ServiceInfo {
 
-        let mut sn = match fs::read_to_string("/var/lib/dbus/machine-id") {
-            Ok(id) => id.to_uppercase(),
-            Err(e) => {
-                eprintln!("WARNING: Failed to read machine-id: {}. Using 'UNKNOWN'.", e);
-                "UNKNOWN".to_string()
-            }
-        };
-        trim_newline(&mut sn);
+        let sn = device_id.to_string();
         let info = uname::uname().expect("CRITICAL: Failed to get system info via uname. This should never fail on a Linux system.");
 
         // Build configuration response
@@ -148,7 +260,21 @@ impl ServiceInfo {
             temp_extract_path: settings.temp_extract_path.to_string_lossy().to_string(),
             staging_path: settings.staging_path.to_string_lossy().to_string(),
             rollback_path: settings.rollback_path.to_string_lossy().to_string(),
+            releases_path: settings.releases_path.to_string_lossy().to_string(),
+            keep_releases: settings.keep_releases,
             rest_api_bind_address: settings.rest_api_bind_address.clone(),
+            rest_api_enable_tcp: settings.rest_api_enable_tcp,
+            rest_api_port: settings.rest_api_port,
+            rest_api_socket_path: settings.rest_api_socket_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+            rest_api_socket_mode: format!("{:04o}", settings.rest_api_socket_mode),
+            rest_api_allowed_uids: settings.rest_api_allowed_uids.clone(),
+            rest_api_allowed_gids: settings.rest_api_allowed_gids.clone(),
+            rest_api_auth: settings.rest_api_auth,
+            rest_api_token_file: settings.rest_api_token_file.as_ref().map(|p| p.to_string_lossy().to_string()),
+            rest_api_tls_cert_path: settings.rest_api_tls_cert_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+            rest_api_tls_key_path: settings.rest_api_tls_key_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+            rest_api_tls_client_ca_path: settings.rest_api_tls_client_ca_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+            ready_requires_cloud: settings.ready_requires_cloud,
             update_server_address: settings.update_server_address.clone(),
             update_server_port: settings.update_server_port,
             use_authentication: settings.use_authentication,
@@ -156,9 +282,21 @@ impl ServiceInfo {
             connect_retry_seconds: settings.connect_retry_seconds,
             update_apply_timeout_seconds: settings.update_apply_timeout_seconds,
             auth_max_retries: settings.auth_max_retries,
+            auth_retry_minutes: settings.auth_retry_minutes,
+            device_key_path: settings.device_key_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+            key_wrap_algorithm: settings.key_wrap_algorithm,
+            device_id_source: settings.device_id_source.to_string(),
             auto_download_updates: settings.auto_download_updates,
+            download_max_retries: settings.download_max_retries,
+            require_signed_updates: settings.require_signed_updates,
+            trusted_signing_keys: settings.trusted_signing_keys.iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect(),
             app_is_systemd_service: settings.app_is_systemd_service,
             app_service_name: settings.app_service_name.clone(),
+            health_check_mode: settings.health_check_mode,
+            health_check_seconds: settings.health_check_seconds,
+            health_check_url: settings.health_check_url.clone(),
         };
 
         ServiceInfo {
@@ -181,9 +319,9 @@ impl ServiceInfo {
                 os_name: info.sysname,
                 machine: info.machine
             },
-            public_key: Crypto::get_public_key_pem(None).unwrap_or_else(|e| {
-                eprintln!("WARNING: Failed to get public key: {}. Using placeholder.", e);
-                eprintln!("  Looked in ~/.ssh/id_rsa.pub (or /root/.ssh/id_rsa.pub)");
+            public_key: Crypto::get_public_key_pem(Some(settings.auth_key_path())).unwrap_or_else(|e| {
+                eprintln!("WARNING: Failed to get public key: {:#}. Using placeholder.", e);
+                eprintln!("  Looked for {:?} (or the .pub file next to it)", settings.auth_key_path());
                 eprintln!("  You can configure the SSH key path in /etc/meadow.conf using the 'ssh_key_path' setting.");
                 "[No Public Key]".to_string()
             }),
@@ -197,315 +335,769 @@ impl RestServer {
         RestServer { }
     }
    
-    pub async fn start(&mut self, store: Arc<Mutex<UpdateStore>>, settings: crate::cloud_settings::CloudSettings, bind_address: &str) -> std::io::Result<()> {
+    pub async fn start(&mut self, store: Arc<Mutex<UpdateStore>>, service_status: Arc<Mutex<ServiceStatus>>, device_id: DeviceId, settings: crate::cloud_settings::CloudSettings, bind_address: &str) -> std::io::Result<()> {
 
-        println!("Meadow daemon listening for REST calls on {}:{}", bind_address, PORT);
+        let auth = RestAuth::from_settings(&settings);
+        if auth.is_enabled() {
+            println!("REST API authentication enabled");
+        }
+        let peer_policy = PeerPolicy::new(settings.rest_api_allowed_uids.clone(), settings.rest_api_allowed_gids.clone());
+        let enable_tcp = settings.rest_api_enable_tcp;
+        if enable_tcp && !auth.is_enabled()
+            && (!settings.rest_api_allowed_uids.is_empty() || !settings.rest_api_allowed_gids.is_empty()) {
+            eprintln!("WARNING: rest_api_allowed_uids/gids only apply to the Unix socket; TCP clients can still call every endpoint. Turn on rest_api_auth or set rest_api_enable_tcp no.");
+        }
+        let port = settings.rest_api_port;
+        let tls = rest_tls::server_config(&settings)
+            .map_err(std::io::Error::other)?;
+        let require_client_cert = settings.rest_api_tls_client_ca_path.is_some();
+        let socket = settings.rest_api_socket_path.clone().map(|path| (path, settings.rest_api_socket_mode));
 
-        HttpServer::new(move || {
+        let mut server = HttpServer::new(move || {
             App::new()
                 .app_data(web::Data::new(store.clone()))
+                .app_data(web::Data::new(service_status.clone()))
+                .app_data(web::Data::new(device_id.clone()))
                 .app_data(web::Data::new(settings.clone()))
-                .service(
-                    web::scope("api")
-                        .route("/info", web::get().to(Self::get_daemon_info))
-                        .route("/updates", web::get().to(Self::get_updates))
-                        .route("/updates/{id}", web::put().to(Self::update_action))
-                        .route("/updates", web::delete().to(Self::clear_update_store))
-                        .route("/apply", web::put().to(Self::apply_extracted))
-                        .route("/files", web::get().to(Self::list_files))
-                        .route("/files/{path:.*}", web::get().to(Self::list_files))
-                )
-        })
-            .bind(format!("{}:{}", bind_address, PORT))?
-            .run()
-            .await
-    }
+                .app_data(web::Data::new(auth.clone()))
+                .app_data(web::Data::new(peer_policy.clone()))
+                .app_data(web::JsonConfig::default()
+                    .error_handler(|e, _| ApiError::InvalidRequest(e.to_string()).into()))
+                .wrap(middleware::from_fn(metrics::track_requests))
+                .configure(Self::routes)
+        });
+        #[cfg(unix)]
+        {
+            server = server.on_connect(rest_auth::record_peer_credentials);
+        }
 
-    async fn clear_update_store(
-        store: web::Data<Arc<Mutex<UpdateStore>>>)
-        -> Result<HttpResponse, Error> {
+        if enable_tcp {
+            let address = format!("{}:{}", bind_address, port);
+            match tls {
+                Some(tls) => {
+                    println!("Meadow daemon listening for REST calls on https://{}{}", address,
+                        if require_client_cert { " (client certificate required)" } else { "" });
+                    server = server.bind_rustls_0_23(address, tls)?;
+                },
+                None => {
+                    println!("Meadow daemon listening for REST calls on {}", address);
+                    server = server.bind(address)?;
+                },
+            }
+        }
 
-        println!("REST CLEAR UPDATE STORE");
+        #[cfg(unix)]
+        if let Some((path, mode)) = &socket {
+            use std::os::unix::fs::PermissionsExt;
 
-        match store.lock() {
-            Ok(mut s) => {
-                s.clear();
-                Ok(HttpResponse::Ok().finish())
-            },
-            Err(e) => {
-                eprintln!("ERROR: Failed to lock store: {}", e);
-                Ok(HttpResponse::InternalServerError().body("Failed to lock store"))
-            }
+            // bind_uds() skips the on_connect hook, so bind the listener here and hand it over
+            Self::remove_stale_socket(path)?;
+            let listener = std::os::unix::net::UnixListener::bind(path)?;
+            fs::set_permissions(path, fs::Permissions::from_mode(*mode))?;
+            server = server.listen_uds(listener)?;
+            println!("Meadow daemon listening for REST calls on unix:{} (mode {:04o})", path.display(), mode);
+        }
+
+        if !enable_tcp && socket.is_none() {
+            eprintln!("WARNING: rest_api_enable_tcp is off and no rest_api_socket_path is set; the REST API is unreachable");
         }
+
+        server.run().await
     }
 
-    async fn get_daemon_info(
-        settings: web::Data<crate::cloud_settings::CloudSettings>)
-        -> Result<HttpResponse, Error> {
-        Ok(HttpResponse::Ok().json(&ServiceInfo::new(&settings)))
+    /// Every route the daemon serves; `App`-wide data, JSON config and request metrics are set up in `start`
+    pub fn routes(cfg: &mut web::ServiceConfig) {
+        cfg
+            // probes carry no token, and these say nothing worth protecting
+            .route("/healthz", web::get().to(get_health))
+            .route("/readyz", web::get().to(get_readiness))
+            .service(
+                web::resource("/metrics")
+                    .wrap(middleware::from_fn(rest_auth::require_read))
+                    .wrap(middleware::from_fn(rest_auth::require_token))
+                    .route(web::get().to(get_metrics))
+            )
+            // the token is checked for the whole scope; each resource checks its own scope once
+            // it has been routed, and where methods need different scopes they get separate resources
+            .service(
+                web::scope("api")
+                    .wrap(middleware::from_fn(rest_auth::require_token))
+                    .wrap(middleware::from_fn(rest_auth::require_peer))
+                    .service(web::resource("/info")
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(get_daemon_info)))
+                    .service(web::resource("/status")
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(get_service_status)))
+                    .service(web::resource("/cloud/reconnect")
+                        .wrap(middleware::from_fn(rest_auth::require_admin))
+                        .route(web::post().to(reconnect_cloud)))
+                    .service(web::resource("/provision")
+                        .guard(guard::Get())
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(get_provision_info)))
+                    .service(web::resource("/provision")
+                        .wrap(middleware::from_fn(rest_auth::require_admin))
+                        .route(web::post().to(provision_device)))
+                    .service(web::resource("/updates")
+                        .guard(guard::Get())
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(get_updates)))
+                    .service(web::resource("/updates")
+                        .wrap(middleware::from_fn(rest_auth::require_download))
+                        .route(web::delete().to(clear_update_store)))
+                    // guarded so GET /api/updates/confirm still reaches get_update
+                    .service(web::resource("/updates/confirm")
+                        .guard(guard::Post())
+                        .wrap(middleware::from_fn(rest_auth::require_apply))
+                        .route(web::post().to(confirm_update)))
+                    .service(web::resource("/updates/{id}")
+                        .guard(guard::Get())
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(get_update)))
+                    .service(web::resource("/updates/{id}")
+                        .wrap(middleware::from_fn(rest_auth::require_download))
+                        .route(web::put().to(update_action)))
+                    .service(web::resource("/apply")
+                        .wrap(middleware::from_fn(rest_auth::require_apply))
+                        .route(web::put().to(apply_extracted)))
+                    .service(web::resource("/rollback")
+                        .wrap(middleware::from_fn(rest_auth::require_apply))
+                        .route(web::post().to(rollback)))
+                    .service(web::resource("/releases")
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(get_releases)))
+                    .service(web::resource("/releases/{id}")
+                        .wrap(middleware::from_fn(rest_auth::require_apply))
+                        .route(web::put().to(switch_release)))
+                    .service(web::resource("/events")
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(stream_events)))
+                    .service(web::resource("/files")
+                        .wrap(middleware::from_fn(rest_auth::require_files))
+                        .route(web::get().to(list_files)))
+                    .service(web::resource("/files/{path:.*}")
+                        .wrap(middleware::from_fn(rest_auth::require_files))
+                        .route(web::get().to(list_files_in)))
+                    .service(web::resource("/openapi.json")
+                        .wrap(middleware::from_fn(rest_auth::require_read))
+                        .route(web::get().to(get_openapi)))
+            );
     }
 
-    async fn update_action(
-        store: web::Data<Arc<Mutex<UpdateStore>>>,
-        data: web::Json<UpdateAction>, id: web::Path<String>) 
-        -> impl Responder {
-
-        println!("REST PUT UPDATE");
-        
-        match data.action.as_str() {
-            "download" => {
-                println!("Download MPAK for {}", id);
-                match store.lock() {
-                    Ok(s) => {
-                        match s.retrieve_update(&id).await {
-                            Ok(_result) => {
-                                HttpResponse::Ok().finish()
-                            },
-                            Err(msg) => {
-                                println!("Error sending MPAK for {}: {}", id, msg);
-                                HttpResponse::NotFound().body(msg)
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("ERROR: Failed to lock store: {}", e);
-                        HttpResponse::InternalServerError().body("Failed to lock store")
-                    }
-                }
-            },
-            "apply" => {
-                println!("Apply update {}", id);
-                let pid = data.pid.unwrap_or(0);
-                let  app_path;
-
-                match &data.app_dir {
-                    None => {
-                        match fs::read_link(format!("/proc/{}/exe", pid)) {
-                            Ok(path) => {
-                                app_path = path;
-                            },
-                            Err(_) => {
-                                let msg = format!("Caller sent in an invalid PID {}", pid);
-                                println!("{}", msg);
-                                return HttpResponse::NotFound().body(msg);
-                            }
-                        }
-                    },
-                    Some(p) => {
-                        // TODO verify the provided path is valid?
-                        app_path = PathBuf::from(p);
-                    }
-                }
+    /// Create the socket's directory and remove a socket left behind by a previous run
+    #[cfg(unix)]
+    fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+        use std::os::unix::fs::FileTypeExt;
 
-                if pid != 0 {
-                    // note: this will launch a thread to wait and apply
-                    match store.lock() {
-                        Ok(s) => {
-                            match s.apply_update(&id, &app_path, pid, &data.command).await {
-                                Ok(_result) => {
-                                    return HttpResponse::Ok().finish();
-                                },
-                                Err(msg) => {
-                                    return HttpResponse::NotFound().body(msg);
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            eprintln!("ERROR: Failed to lock store: {}", e);
-                            return HttpResponse::InternalServerError().body("Failed to lock store");
-                        }
-                    }
-                }
-                else {
-                    // TODO: should we support non-pid apply calls?
-                    let msg = format!("Caller did not provide a valid PID");
-                    println!("{}", msg);
-                    return HttpResponse::BadRequest().body(msg);
-                }
-            },
-            _ => {
-                println!("Unknown action request: {}", data.action);
-                HttpResponse::NotFound().finish()
-            }
+        if let Some(parent) = path.parent()
+            && !parent.exists() {
+            fs::create_dir_all(parent)?;
         }
+
+        if let Ok(meta) = fs::symlink_metadata(path)
+            && meta.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
     }
-    
-    async fn get_updates(
-        store: web::Data<Arc<Mutex<UpdateStore>>>)
-        -> Result<HttpResponse, Error> { //actix_web::Result<impl Responder> {
+}
 
-        // open the store
-        let updates = match store.lock() {
-            Ok(s) => s.get_all_messages(),
-            Err(e) => {
-                eprintln!("ERROR: Failed to lock store: {}", e);
-                return Ok(HttpResponse::InternalServerError().body("Failed to lock store"));
-            }
+/// Forget all updates
+#[utoipa::path(delete, path = "/api/updates", tag = "updates",
+    responses((status = 200, description = "Update store cleared")))]
+async fn clear_update_store(
+    store: web::Data<Arc<Mutex<UpdateStore>>>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST CLEAR UPDATE STORE");
+
+    let mut s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+    s.clear();
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Called by the app once it is up and running after an update (health_check_mode confirm)
+#[utoipa::path(post, path = "/api/updates/confirm", tag = "updates",
+    responses((status = 200, description = "Update confirmed")))]
+async fn confirm_update(
+    store: web::Data<Arc<Mutex<UpdateStore>>>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST CONFIRM UPDATE");
+
+    let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+    s.confirm_update();
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Server-Sent Events stream of `DaemonEvent`s; the event name is the `type` field
+#[utoipa::path(get, path = "/api/events", tag = "daemon",
+    responses((status = 200, content_type = "text/event-stream", body = DaemonEvent)))]
+async fn stream_events(
+    store: web::Data<Arc<Mutex<UpdateStore>>>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST EVENT STREAM");
+
+    let receiver = store.lock()
+        .map_err(|e| ApiError::lock_failed("store", e))?
+        .events()
+        .subscribe();
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let chunk = match tokio::time::timeout(Duration::from_secs(EVENT_KEEPALIVE_SECONDS), receiver.recv()).await {
+            Ok(Ok(event)) => format_event(&event),
+            // a slow reader; say so and carry on with the newest events
+            Ok(Err(RecvError::Lagged(missed))) => format!(": {} events dropped\n\n", missed),
+            Ok(Err(RecvError::Closed)) => return None,
+            // idle; a comment line keeps proxies from closing the connection
+            Err(_) => ": keep-alive\n\n".to_string(),
         };
+        Some((Ok::<_, Error>(web::Bytes::from(chunk)), receiver))
+    });
 
-        let mut result: Vec<UpdateDescriptor> = Vec::with_capacity(updates.len());
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events))
+}
 
-        for i in 0..updates.len() {
-            match updates[i].lock() {
-                Ok(update) => result.push(update.clone()),
-                Err(e) => {
-                    eprintln!("WARNING: Failed to lock update descriptor {}: {}", i, e);
-                    // Continue with other updates
-                }
-            }
+fn format_event(event: &DaemonEvent) -> String {
+    match serde_json::to_string(event) {
+        Ok(json) => format!("event: {}\ndata: {}\n\n", event.name(), json),
+        Err(e) => {
+            eprintln!("ERROR: Failed to serialize event: {}", e);
+            String::new()
         }
+    }
+}
+
+/// Daemon version, device details, public key and configuration
+#[utoipa::path(get, path = "/api/info", tag = "daemon",
+    responses((status = 200, body = ServiceInfo)))]
+async fn get_daemon_info(
+    device_id: web::Data<DeviceId>,
+    settings: web::Data<crate::cloud_settings::CloudSettings>)
+    -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(&ServiceInfo::new(&settings, &device_id)))
+}
 
+/// Liveness: answers as long as the process and its REST loop are up
+#[utoipa::path(get, path = "/healthz", tag = "health", security(()),
+    responses((status = 200, body = HealthResponse)))]
+async fn get_health() -> HttpResponse {
+    HttpResponse::Ok().json(HealthResponse::live())
+}
+
+/// Readiness: update store writable, update service running and, with ready_requires_cloud, connected
+#[utoipa::path(get, path = "/readyz", tag = "health", security(()),
+    responses(
+        (status = 200, body = HealthResponse),
+        (status = 503, description = "A component is failing; see `components`", body = HealthResponse),
+    ))]
+async fn get_readiness(
+    service_status: web::Data<Arc<Mutex<ServiceStatus>>>,
+    settings: web::Data<crate::cloud_settings::CloudSettings>)
+    -> HttpResponse {
 
-        // retrieve update info
-        println!("  sending {} results...", result.len());
+    // a poisoned lock means the service thread died holding it; its stale heartbeat says so
+    let readiness = {
+        let status = service_status.lock().unwrap_or_else(|e| e.into_inner());
+        HealthResponse::ready(&settings, &status)
+    };
 
-        Ok(HttpResponse::Ok().json(result))
+    if readiness.is_ok() {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
     }
+}
 
-    async fn apply_extracted(
-        store: web::Data<Arc<Mutex<UpdateStore>>>,
-        data: web::Json<ApplyAction>)
-        -> impl Responder {
+/// Prometheus metrics in text format
+#[utoipa::path(get, path = "/metrics", tag = "daemon",
+    responses((status = 200, description = "Prometheus text exposition format", content_type = "text/plain")))]
+async fn get_metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics().render())
+}
 
-        println!("REST APPLY EXTRACTED UPDATE");
+/// This document
+#[utoipa::path(get, path = "/api/openapi.json", tag = "daemon",
+    responses((status = 200, description = "OpenAPI 3.1 document", content_type = "application/json")))]
+async fn get_openapi() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(ApiDoc::json())
+}
 
-        let pid = match data.pid {
-            Some(p) if p > 0 => p,
-            _ => {
-                let msg = "PID is required and must be greater than 0";
-                println!("ERROR: {}", msg);
-                return HttpResponse::BadRequest().body(msg);
-            }
-        };
+/// Cloud connection state, auth failures, last error and broker connectivity
+#[utoipa::path(get, path = "/api/status", tag = "daemon",
+    responses((status = 200, body = StatusResponse)))]
+async fn get_service_status(
+    service_status: web::Data<Arc<Mutex<ServiceStatus>>>,
+    settings: web::Data<crate::cloud_settings::CloudSettings>)
+    -> Result<HttpResponse, ApiError> {
 
-        // Determine application directory and executable path
-        let (app_dir, executable_path) = match &data.app_dir {
-            None => {
-                // Auto-detect from /proc/{pid}/exe
-                match fs::read_link(format!("/proc/{}/exe", pid)) {
-                    Ok(exe_path) => {
-                        let dir = match exe_path.parent() {
-                            Some(parent) => parent.to_path_buf(),
-                            None => {
-                                let msg = format!("Failed to get directory from executable path: {:?}", exe_path);
-                                println!("ERROR: {}", msg);
-                                return HttpResponse::BadRequest().body(msg);
-                            }
-                        };
-                        (dir, exe_path)
-                    },
-                    Err(e) => {
-                        let msg = format!("Failed to determine application path from PID {}: {}", pid, e);
-                        println!("ERROR: {}", msg);
-                        return HttpResponse::BadRequest().body(msg);
-                    }
-                }
-            },
-            Some(dir_str) => {
-                // Use provided app_dir as the application directory
-                let dir = PathBuf::from(dir_str.trim_end_matches('/'));
-
-                // Construct executable path from app_dir + executable
-                let executable = match &data.executable {
-                    Some(exe) => exe,
-                    None => {
-                        let msg = "executable is required when app_dir is provided";
-                        println!("ERROR: {}", msg);
-                        return HttpResponse::BadRequest().body(msg);
-                    }
-                };
-
-                let exe_path = dir.join(executable);
-                (dir, exe_path)
-            }
-        };
+    let status = service_status.lock().map_err(|e| ApiError::lock_failed("service status", e))?;
+    Ok(HttpResponse::Ok().json(StatusResponse {
+        listener_enabled: settings.enable_mqtt_listener,
+        service: status.clone(),
+    }))
+}
+
+/// Retry authentication after AuthenticationFailed
+#[utoipa::path(post, path = "/api/cloud/reconnect", tag = "daemon",
+    responses(
+        (status = 202, description = "`reconnect_requested`", body = ActionResult),
+        (status = 409, description = "`listener_disabled` or `reconnect_rejected`", body = ErrorResponse),
+    ))]
+async fn reconnect_cloud(
+    service_status: web::Data<Arc<Mutex<ServiceStatus>>>,
+    settings: web::Data<crate::cloud_settings::CloudSettings>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST CLOUD RECONNECT");
+
+    if !settings.enable_mqtt_listener {
+        return Err(ApiError::ListenerDisabled);
+    }
+
+    let mut status = service_status.lock().map_err(|e| ApiError::lock_failed("service status", e))?;
+    status.request_reconnect()
+        .map_err(ApiError::ReconnectRejected)?;
+    Ok(HttpResponse::Accepted().json(ActionResult::new("reconnect_requested", "Reconnect requested".to_string(), None)))
+}
+
+/// Device ID and public key (PEM and OpenSSH) for registration
+#[utoipa::path(get, path = "/api/provision", tag = "provisioning",
+    responses(
+        (status = 200, body = ProvisionInfo),
+        (status = 409, description = "`no_device_key`", body = ErrorResponse),
+    ))]
+async fn get_provision_info(
+    device_id: web::Data<DeviceId>,
+    settings: web::Data<crate::cloud_settings::CloudSettings>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST GET PROVISION");
+
+    let (public_key, public_key_openssh) = device_public_keys(&settings)?;
+    Ok(HttpResponse::Ok().json(ProvisionInfo {
+        device_id: device_id.to_string(),
+        public_key,
+        public_key_openssh,
+    }))
+}
+
+fn device_public_keys(settings: &crate::cloud_settings::CloudSettings) -> Result<(String, String), ApiError> {
+    let key_path = settings.auth_key_path();
+    Crypto::get_public_key_pem(Some(key_path))
+        .and_then(|pem| Ok((pem, Crypto::get_public_key_openssh(Some(key_path))?)))
+        .map_err(|e| {
+            eprintln!("ERROR: Failed to get public key: {:#}", e);
+            ApiError::NoDeviceKey(key_path.display().to_string())
+        })
+}
+
+/// Register this device with auth_server_address
+#[utoipa::path(post, path = "/api/provision", tag = "provisioning",
+    request_body = ProvisionRequest,
+    responses(
+        (status = 200, body = ProvisionResult),
+        (status = 409, description = "`no_device_key`", body = ErrorResponse),
+        (status = 502, description = "Meadow.Cloud could not be reached or rejected the registration", body = ErrorResponse),
+    ))]
+async fn provision_device(
+    service_status: web::Data<Arc<Mutex<ServiceStatus>>>,
+    device_id: web::Data<DeviceId>,
+    settings: web::Data<crate::cloud_settings::CloudSettings>,
+    data: web::Json<ProvisionRequest>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST POST PROVISION");
+
+    let device_id = device_id.to_string();
+    let (_, public_key) = device_public_keys(&settings)?;
+
+    // the same registration Meadow.CLI's `meadow device provision` sends, with the user's token
+    let endpoint = format!("{}/api/devices/register", settings.auth_server_url());
+    let name = data.name.clone()
+        .or_else(|| uname::uname().ok().map(|info| info.nodename))
+        .unwrap_or_else(|| device_id.clone());
+    let body = serde_json::json!({
+        "id": device_id,
+        "orgId": data.org_id,
+        "collectionId": data.collection_id,
+        "name": name,
+        "pubKey": public_key,
+    });
 
-        println!("Application directory: {:?}", app_dir);
-        println!("Executable path: {:?}", executable_path);
-        println!("Waiting for PID: {}", pid);
-        if let Some(ref cmd) = data.command {
-            println!("Restart command: {}", cmd);
+    println!("Provisioning device {} at {}", device_id, endpoint);
+
+    let response = match reqwest::Client::new().post(&endpoint)
+        .bearer_auth(&data.token)
+        .json(&body)
+        .send()
+        .await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("ERROR: Provisioning request failed: {}", e);
+            return Err(ApiError::Upstream(format!("Failed to reach {}: {}", endpoint, e)));
         }
+    };
+
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        eprintln!("ERROR: Provisioning rejected: {} {}", status, text);
+        return Err(ApiError::Upstream(format!("Provisioning rejected ({}): {}", status, text)));
+    }
+
+    // A device that couldn't log in before it was registered can try again straight away
+    if settings.enable_mqtt_listener
+        && let Ok(mut status) = service_status.lock()
+        && status.state == crate::update_service::UpdateState::AuthenticationFailed {
+        let _ = status.request_reconnect();
+    }
 
-        match store.lock() {
-            Ok(s) => {
-                match s.apply_extracted_update(&app_dir, &executable_path, pid, &data.command).await {
-                    Ok(_) => {
-                        println!("Apply operation started successfully");
-                        HttpResponse::Ok().body("Update apply started")
-                    },
-                    Err(msg) => {
-                        println!("ERROR: Failed to apply update: {}", msg);
-                        HttpResponse::InternalServerError().body(msg)
-                    }
+    Ok(HttpResponse::Ok().json(ProvisionResult {
+        device_id,
+        public_key,
+    }))
+}
+
+/// Download (in the background) or apply an update
+#[utoipa::path(put, path = "/api/updates/{id}", tag = "updates",
+    params(("id" = String, Path, description = "MPAK ID")),
+    request_body = UpdateAction,
+    responses(
+        (status = 200, description = "Apply started"),
+        (status = 202, description = "Download started; progress shows up on GET /api/updates"),
+        (status = 400, description = "`invalid_request` (no valid PID for apply) or `unknown_action`", body = ErrorResponse),
+        (status = 403, description = "The token lacks the apply scope", body = ErrorResponse),
+        (status = 404, description = "`update_not_found` or `pid_not_found`", body = ErrorResponse),
+        (status = 409, description = "`download_in_progress` or `update_not_downloaded`", body = ErrorResponse),
+        (status = 422, description = "`bad_package` or `bad_signature`", body = ErrorResponse),
+    ))]
+async fn update_action(
+    req: HttpRequest,
+    store: web::Data<Arc<Mutex<UpdateStore>>>,
+    data: web::Json<UpdateAction>, id: web::Path<String>) 
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST PUT UPDATE");
+    
+    match data.action.as_str() {
+        "download" => {
+            println!("Download MPAK for {}", id);
+            let job = store.lock()
+                .map_err(|e| ApiError::lock_failed("store", e))?
+                .begin_download(&id)
+                .inspect_err(|e| println!("Error sending MPAK for {}: {}", id, e))?;
+
+            // the transfer runs in the background; progress shows up on GET /api/updates
+            actix_web::rt::spawn(async move {
+                let id = job.update_id().to_string();
+                match job.run().await {
+                    Ok(size) => println!("Download of {} completed: {} bytes", id, size),
+                    Err(e) => println!("Error sending MPAK for {}: {}", id, e)
                 }
-            },
+            });
+
+            Ok(HttpResponse::Accepted().finish())
+        },
+        "apply" => {
+            RestAuth::authorize(&req, Scope::Apply).map_err(ApiError::Forbidden)?;
+            println!("Apply update {}", id);
+
+            // TODO: should we support non-pid apply calls?
+            let pid = match data.pid {
+                Some(pid) if pid != 0 => pid,
+                _ => {
+                    println!("Caller did not provide a valid PID");
+                    return Err(ApiError::InvalidRequest("Caller did not provide a valid PID".to_string()));
+                }
+            };
+
+            let app_path = match &data.app_dir {
+                // TODO verify the provided path is valid?
+                Some(p) => PathBuf::from(p),
+                None => fs::read_link(format!("/proc/{}/exe", pid))
+                    .map_err(|e| {
+                        println!("Caller sent in an invalid PID {}", pid);
+                        ApiError::PidNotFound { pid, reason: e.to_string() }
+                    })?,
+            };
+
+            // note: this will launch a thread to wait and apply
+            let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+            s.apply_update(&id, &app_path, pid, &data.command).await?;
+            Ok(HttpResponse::Ok().finish())
+        },
+        _ => {
+            println!("Unknown action request: {}", data.action);
+            Err(ApiError::UnknownAction(data.action.clone()))
+        }
+    }
+}
+
+/// Known updates, with progress for those being downloaded
+#[utoipa::path(get, path = "/api/updates", tag = "updates",
+    responses((status = 200, body = [UpdateInfo])))]
+async fn get_updates(
+    store: web::Data<Arc<Mutex<UpdateStore>>>)
+    -> Result<HttpResponse, ApiError> { //actix_web::Result<impl Responder> {
+
+    // open the store
+    let (updates, progress) = {
+        let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+        let updates = s.get_all_messages();
+        let progress: Vec<Option<DownloadProgress>> = updates.iter()
+            .map(|u| u.lock().ok().and_then(|d| s.get_download_progress(&d.mpak_id)))
+            .collect();
+        (updates, progress)
+    };
+
+    let mut result: Vec<UpdateInfo> = Vec::with_capacity(updates.len());
+
+    for i in 0..updates.len() {
+        match updates[i].lock() {
+            Ok(update) => result.push(UpdateInfo {
+                descriptor: update.clone(),
+                progress: progress[i].clone(),
+            }),
             Err(e) => {
-                eprintln!("ERROR: Failed to lock store: {}", e);
-                HttpResponse::InternalServerError().body("Failed to lock store")
+                eprintln!("WARNING: Failed to lock update descriptor {}: {}", i, e);
+                // Continue with other updates
             }
         }
     }
 
-    async fn list_files(
-        settings: web::Data<crate::cloud_settings::CloudSettings>,
-        path: web::Path<String>,
-    ) -> Result<HttpResponse, Error> {
-        let requested_path = path.into_inner();
-        let requested_path = if requested_path.is_empty() {
-            None
-        } else {
-            Some(requested_path.as_str())
-        };
 
-        // Validate and resolve path
-        let target_path = match validate_and_resolve_path(&settings.meadow_root, requested_path) {
-            Ok(p) => p,
-            Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
-        };
+    // retrieve update info
+    println!("  sending {} results...", result.len());
+
+    Ok(HttpResponse::Ok().json(result))
+}
 
-        // Check if path is a directory
-        if !target_path.is_dir() {
-            return Ok(HttpResponse::BadRequest().body("Path is not a directory"));
+/// A single update
+#[utoipa::path(get, path = "/api/updates/{id}", tag = "updates",
+    params(("id" = String, Path, description = "MPAK ID")),
+    responses(
+        (status = 200, body = UpdateInfo),
+        (status = 404, description = "Update not known", body = ErrorResponse),
+    ))]
+async fn get_update(
+    store: web::Data<Arc<Mutex<UpdateStore>>>,
+    id: web::Path<String>)
+    -> Result<HttpResponse, ApiError> {
+
+    let (update, progress) = {
+        let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+        (s.get_message(id.to_string()).cloned(), s.get_download_progress(&id))
+    };
+
+    let descriptor = update
+        .ok_or_else(|| UpdateError::NotFound(id.to_string()))?
+        .lock()
+        .map_err(|e| ApiError::lock_failed("update descriptor", e))?
+        .clone();
+
+    Ok(HttpResponse::Ok().json(UpdateInfo { descriptor, progress }))
+}
+
+/// Apply the update already extracted to temp_extract_path once the app exits
+#[utoipa::path(put, path = "/api/apply", tag = "updates",
+    request_body = ApplyAction,
+    responses(
+        (status = 200, description = "`apply_started`", body = ActionResult),
+        (status = 400, description = "Missing PID or the app location couldn't be worked out", body = ErrorResponse),
+        (status = 404, description = "`pid_not_found`", body = ErrorResponse),
+        (status = 409, description = "`update_not_extracted`", body = ErrorResponse),
+        (status = 422, description = "`bad_signature`: refused while require_signed_updates is on", body = ErrorResponse),
+    ))]
+async fn apply_extracted(
+    store: web::Data<Arc<Mutex<UpdateStore>>>,
+    data: web::Json<ApplyAction>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST APPLY EXTRACTED UPDATE");
+
+    let pid = match data.pid {
+        Some(p) if p > 0 => p,
+        _ => {
+            let msg = "PID is required and must be greater than 0";
+            println!("ERROR: {}", msg);
+            return Err(ApiError::InvalidRequest(msg.to_string()));
         }
+    };
 
-        // Read directory entries
-        let entries = match fs::read_dir(&target_path) {
-            Ok(e) => e,
-            Err(e) => return Ok(HttpResponse::InternalServerError()
-                .body(format!("Failed to read directory: {}", e))),
-        };
+    // Determine application directory and executable path
+    let (app_dir, executable_path) = resolve_app_location(Some(pid), &data.app_dir, &data.executable)
+        .inspect_err(|e| println!("ERROR: {}", e))?;
 
-        // Collect file information
-        let mut files: Vec<FileInfo> = Vec::new();
-        for entry in entries {
-            if let Ok(entry) = entry {
-                if let Ok(metadata) = entry.metadata() {
-                    files.push(FileInfo {
-                        name: entry.file_name().to_string_lossy().to_string(),
-                        is_directory: metadata.is_dir(),
-                        size: if metadata.is_file() { metadata.len() } else { 0 },
-                    });
-                }
-            }
+    println!("Application directory: {:?}", app_dir);
+    println!("Executable path: {:?}", executable_path);
+    println!("Waiting for PID: {}", pid);
+    if let Some(ref cmd) = data.command {
+        println!("Restart command: {}", cmd);
+    }
+
+    let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+    s.apply_extracted_update(&app_dir, &executable_path, pid, &data.command).await
+        .inspect_err(|e| println!("ERROR: Failed to apply update: {}", e))?;
+    println!("Apply operation started successfully");
+    Ok(HttpResponse::Ok().json(ActionResult::new("apply_started", "Update apply started".to_string(), None)))
+}
+
+/// Restore the previous app version
+#[utoipa::path(post, path = "/api/rollback", tag = "releases",
+    request_body = RestartAction,
+    responses(
+        (status = 200, description = "`rollback_started`", body = ActionResult),
+        (status = 400, description = "The app location couldn't be worked out", body = ErrorResponse),
+        (status = 404, description = "`pid_not_found`", body = ErrorResponse),
+        (status = 409, description = "`no_previous_version`", body = ErrorResponse),
+    ))]
+async fn rollback(
+    store: web::Data<Arc<Mutex<UpdateStore>>>,
+    data: web::Json<RestartAction>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST ROLLBACK");
+
+    let pid = data.pid.filter(|p| *p > 0);
+    let (app_dir, executable_path) = resolve_app_location(pid, &data.app_dir, &data.executable)
+        .inspect_err(|e| println!("ERROR: {}", e))?;
+
+    println!("Application directory: {:?}", app_dir);
+    println!("Executable path: {:?}", executable_path);
+
+    let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+    match s.rollback_update(&app_dir, &executable_path, pid, &data.command) {
+        Ok(Some(id)) => Ok(HttpResponse::Ok().json(ActionResult::new("rollback_started",
+            format!("Rollback of update {} started", id), Some(serde_json::json!({ "id": id }))))),
+        Ok(None) => Ok(HttpResponse::Ok().json(ActionResult::new("rollback_started", "Rollback started".to_string(), None))),
+        Err(e) => {
+            println!("ERROR: Failed to roll back: {}", e);
+            Err(e.into())
         }
+    }
+}
 
-        // Sort: directories first, then alphabetically
-        files.sort_by(|a, b| {
-            match (a.is_directory, b.is_directory) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-            }
-        });
+/// App versions kept under releases_path
+#[utoipa::path(get, path = "/api/releases", tag = "releases",
+    responses((status = 200, body = [Release])))]
+async fn get_releases(
+    store: web::Data<Arc<Mutex<UpdateStore>>>)
+    -> Result<HttpResponse, ApiError> {
 
-        // Build response
-        let response = FileListResponse {
-            path: requested_path.unwrap_or("").to_string(),
-            files,
-        };
+    println!("REST GET RELEASES");
+
+    let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+    Ok(HttpResponse::Ok().json(s.list_releases()))
+}
+
+/// Switch the app to a kept release
+#[utoipa::path(put, path = "/api/releases/{id}", tag = "releases",
+    params(("id" = String, Path, description = "Release ID")),
+    request_body = RestartAction,
+    responses(
+        (status = 200, description = "`release_switch_started`", body = ActionResult),
+        (status = 400, description = "The app location couldn't be worked out", body = ErrorResponse),
+        (status = 404, description = "`release_not_found` or `pid_not_found`", body = ErrorResponse),
+        (status = 409, description = "`releases_disabled`", body = ErrorResponse),
+    ))]
+async fn switch_release(
+    store: web::Data<Arc<Mutex<UpdateStore>>>,
+    data: web::Json<RestartAction>, id: web::Path<String>)
+    -> Result<HttpResponse, ApiError> {
+
+    println!("REST SWITCH RELEASE {}", id);
+
+    let pid = data.pid.filter(|p| *p > 0);
+    let (app_dir, executable_path) = resolve_app_location(pid, &data.app_dir, &data.executable)
+        .inspect_err(|e| println!("ERROR: {}", e))?;
+
+    let s = store.lock().map_err(|e| ApiError::lock_failed("store", e))?;
+    s.switch_release(&id, &app_dir, &executable_path, pid, &data.command)
+        .inspect_err(|e| println!("ERROR: Failed to switch release: {}", e))?;
+    Ok(HttpResponse::Ok().json(ActionResult::new("release_switch_started",
+        format!("Switch to release {} started", id), Some(serde_json::json!({ "id": id.as_str() })))))
+}
+
+/// Contents of meadow_root
+#[utoipa::path(get, path = "/api/files", tag = "files",
+    responses(
+        (status = 200, body = FileListResponse),
+        (status = 400, description = "Not a directory", body = ErrorResponse),
+    ))]
+async fn list_files(
+    settings: web::Data<crate::cloud_settings::CloudSettings>)
+    -> Result<HttpResponse, ApiError> {
+    list_directory(&settings, None)
+}
+
+/// Contents of a directory under meadow_root
+#[utoipa::path(get, path = "/api/files/{path}", tag = "files",
+    params(("path" = String, Path, description = "Directory relative to meadow_root")),
+    responses(
+        (status = 200, body = FileListResponse),
+        (status = 400, description = "Not a directory, or outside meadow_root", body = ErrorResponse),
+    ))]
+async fn list_files_in(
+    settings: web::Data<crate::cloud_settings::CloudSettings>,
+    path: web::Path<String>)
+    -> Result<HttpResponse, ApiError> {
+    let requested_path = path.into_inner();
+    list_directory(&settings, Some(requested_path.as_str()).filter(|p| !p.is_empty()))
+}
 
-        Ok(HttpResponse::Ok().json(response))
+fn list_directory(settings: &crate::cloud_settings::CloudSettings, requested_path: Option<&str>) -> Result<HttpResponse, ApiError> {
+
+    // Validate and resolve path
+    let target_path = validate_and_resolve_path(&settings.meadow_root, requested_path)
+        .map_err(ApiError::InvalidPath)?;
+
+    // Check if path is a directory
+    if !target_path.is_dir() {
+        return Err(ApiError::InvalidPath("Path is not a directory".to_string()));
+    }
+
+    // Read directory entries
+    let entries = fs::read_dir(&target_path)
+        .map_err(|e| ApiError::Internal(format!("Failed to read directory: {}", e)))?;
+
+    // Collect file information
+    let mut files: Vec<FileInfo> = Vec::new();
+    for entry in entries {
+        if let Ok(entry) = entry {
+            if let Ok(metadata) = entry.metadata() {
+                files.push(FileInfo {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    is_directory: metadata.is_dir(),
+                    size: if metadata.is_file() { metadata.len() } else { 0 },
+                });
+            }
+        }
     }
 
-}
\ No newline at end of file
+    // Sort: directories first, then alphabetically
+    files.sort_by(|a, b| {
+        match (a.is_directory, b.is_directory) {
+            (true, false) => std::cmp::Ordering::Less,
+            (false, true) => std::cmp::Ordering::Greater,
+            _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        }
+    });
+
+    // Build response
+    let response = FileListResponse {
+        path: requested_path.unwrap_or("").to_string(),
+        files,
+    };
+
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,79 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+use rustls::{RootCertStore, ServerConfig, crypto::CryptoProvider, pki_types::{CertificateDer, PrivateKeyDer}, server::WebPkiClientVerifier};
+
+use crate::cloud_settings::CloudSettings;
+
+/// Build the REST server's TLS config from `rest_api_tls_*`
+///
+/// Returns `Ok(None)` when no certificate is configured, so the API stays on plain HTTP.
+/// When `rest_api_tls_client_ca_path` is set, clients must present a certificate signed by
+/// one of the CAs in that file.
+pub fn server_config(settings: &CloudSettings) -> Result<Option<ServerConfig>, String> {
+    match (&settings.rest_api_tls_cert_path, &settings.rest_api_tls_key_path) {
+        (None, None) => {
+            if settings.rest_api_tls_client_ca_path.is_some() {
+                return Err("rest_api_tls_client_ca_path needs rest_api_tls_cert_path and rest_api_tls_key_path".to_string());
+            }
+            Ok(None)
+        },
+        (Some(cert_path), Some(key_path)) =>
+            load_server_config(cert_path, key_path, settings.rest_api_tls_client_ca_path.as_deref()).map(Some),
+        _ => Err("rest_api_tls_cert_path and rest_api_tls_key_path must be set together".to_string()),
+    }
+}
+
+pub fn load_server_config(cert_path: &Path, key_path: &Path, client_ca_path: Option<&Path>) -> Result<ServerConfig, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Failed to set up TLS: {}", e))?;
+
+    let builder = match client_ca_path {
+        Some(ca_path) => builder.with_client_cert_verifier(client_verifier(ca_path, provider)?),
+        None => builder.with_no_client_auth(),
+    };
+
+    builder.with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid TLS certificate/key {:?}, {:?}: {}", cert_path, key_path, e))
+}
+
+fn client_verifier(ca_path: &Path, provider: Arc<CryptoProvider>) -> Result<Arc<dyn rustls::server::danger::ClientCertVerifier>, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca_path)? {
+        roots.add(cert)
+            .map_err(|e| format!("Invalid client CA certificate in {:?}: {}", ca_path, e))?;
+    }
+
+    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .map_err(|e| format!("Failed to set up client certificate verification: {}", e))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let mut reader = open(path)?;
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read certificates from {:?}: {}", path, e))?;
+
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {:?}", path));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let mut reader = open(path)?;
+    rustls_pemfile::private_key(&mut reader)
+        .map_err(|e| format!("Failed to read private key from {:?}: {}", path, e))?
+        .ok_or_else(|| format!("No private key found in {:?}", path))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("Failed to open {:?}: {}", path, e))
+}
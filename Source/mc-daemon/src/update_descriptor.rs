@@ -1,7 +1,25 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use anyhow::{Context, Result};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Where an update is in its lifecycle
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+pub enum UpdateStatus {
+    #[default]
+    Available,
+    Downloading,
+    Downloaded,
+    Verifying,
+    /// Extracted and waiting for the app to exit
+    Staged,
+    Applying,
+    Applied,
+    Failed,
+    RolledBack,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct UpdateDescriptor {
     #[serde(rename = "mpakId")]
     pub mpak_id: String,
@@ -25,8 +43,18 @@ pub struct UpdateDescriptor {
     pub detail: Option<String>,
     #[serde(rename = "updateType")]
     pub update_type: Option<i32>,
+    #[serde(rename = "signature")]
+    pub signature: Option<String>,
     pub retrieved: Option<bool>,
-    pub applied: Option<bool>,    
+    pub applied: Option<bool>,
+    #[serde(rename = "partialOffset")]
+    pub partial_offset: Option<u64>,
+    #[serde(default)]
+    pub status: UpdateStatus,
+    #[serde(rename = "statusChangedAt")]
+    pub status_changed_at: Option<u64>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
 }
 
 impl UpdateDescriptor {
@@ -37,6 +65,7 @@ impl UpdateDescriptor {
             target_devices: None, 
             published_on: "1/1/1980".to_string(), 
             update_type: Some(1), 
+            signature: None,
             version: Some("0.999".to_string()), 
             file_size: 1234, 
             summary: None, 
@@ -44,7 +73,61 @@ impl UpdateDescriptor {
             crc: "".to_string(),
             metadata: None,
             retrieved: None,
-            applied: None
+            applied: None,
+            partial_offset: None,
+            status: UpdateStatus::Available,
+            status_changed_at: None,
+            last_error: None
+        }
+    }
+
+    /// Move to a new lifecycle state, keeping `retrieved` and `applied` in step for older clients
+    pub fn set_status(&mut self, status: UpdateStatus) {
+        self.status = status;
+        self.status_changed_at = Some(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0));
+
+        match status {
+            UpdateStatus::Downloaded => {
+                self.retrieved = Some(true);
+                self.last_error = None;
+            },
+            UpdateStatus::Staged | UpdateStatus::Applying => {
+                self.retrieved = Some(true);
+            },
+            UpdateStatus::Applied => {
+                self.retrieved = Some(true);
+                self.applied = Some(true);
+                self.last_error = None;
+            },
+            UpdateStatus::RolledBack => {
+                self.applied = Some(false);
+            },
+            _ => {}
+        }
+    }
+
+    /// Move to a failure state (Failed or RolledBack), recording why
+    pub fn set_error(&mut self, status: UpdateStatus, error: impl Into<String>) {
+        self.set_status(status);
+        self.last_error = Some(error.into());
+    }
+
+    /// Settle the status of a descriptor loaded from disk
+    ///
+    /// Files written before `status` existed get one inferred from `retrieved`/`applied`, and
+    /// work that a daemon restart cut short (a download, verify or apply) is resolved.
+    pub fn recover_status(&mut self) {
+        match self.status {
+            UpdateStatus::Available if self.applied == Some(true) => self.set_status(UpdateStatus::Applied),
+            UpdateStatus::Available if self.retrieved == Some(true) => self.set_status(UpdateStatus::Downloaded),
+            // an interrupted download keeps its partial file and can be resumed
+            UpdateStatus::Downloading | UpdateStatus::Verifying => self.set_status(UpdateStatus::Available),
+            UpdateStatus::Staged => self.set_status(UpdateStatus::Downloaded),
+            UpdateStatus::Applying => self.set_error(UpdateStatus::Failed, "Apply was interrupted by a daemon restart"),
+            _ => {}
         }
     }
 
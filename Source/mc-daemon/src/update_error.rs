@@ -0,0 +1,58 @@
+use thiserror::Error;
+
+/// Why an `UpdateStore` operation failed
+///
+/// Each variant has a stable `code()` that REST clients can match on; the message is for
+/// people and may change.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum UpdateError {
+    #[error("Update {0} not known")]
+    NotFound(String),
+    #[error("Update {0} has not been downloaded")]
+    NotDownloaded(String),
+    #[error("Update {0} is already being downloaded")]
+    DownloadInProgress(String),
+    /// The transfer failed, or the server refused it
+    #[error("{0}")]
+    DownloadFailed(String),
+    /// Size or CRC mismatch, not a zip, unsafe entries, or no `app` folder
+    #[error("{0}")]
+    BadPackage(String),
+    /// Missing or invalid signature while `require_signed_updates` is on
+    #[error("{0}")]
+    BadSignature(String),
+    /// PUT /api/apply without an update extracted to `temp_extract_path`
+    #[error("{0}")]
+    NotExtracted(String),
+    #[error("{0}")]
+    NoPreviousVersion(String),
+    #[error("Versioned releases are not enabled (keep_releases is 0)")]
+    ReleasesDisabled,
+    #[error("Release '{0}' not found")]
+    ReleaseNotFound(String),
+    /// Reading or writing the store, the package or the app directory failed
+    #[error("{0}")]
+    Io(String),
+    /// A lock was poisoned or similar; not the caller's fault
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl UpdateError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            UpdateError::NotFound(_) => "update_not_found",
+            UpdateError::NotDownloaded(_) => "update_not_downloaded",
+            UpdateError::DownloadInProgress(_) => "download_in_progress",
+            UpdateError::DownloadFailed(_) => "download_failed",
+            UpdateError::BadPackage(_) => "bad_package",
+            UpdateError::BadSignature(_) => "bad_signature",
+            UpdateError::NotExtracted(_) => "update_not_extracted",
+            UpdateError::NoPreviousVersion(_) => "no_previous_version",
+            UpdateError::ReleasesDisabled => "releases_disabled",
+            UpdateError::ReleaseNotFound(_) => "release_not_found",
+            UpdateError::Io(_) => "io_error",
+            UpdateError::Internal(_) => "internal_error",
+        }
+    }
+}
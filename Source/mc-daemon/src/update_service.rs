@@ -1,17 +1,17 @@
-use std::{error::Error, thread::{sleep, self}, sync::{Mutex, Arc, mpsc::{self, Sender, Receiver}}, fs};
+use std::{error::Error, thread, sync::{Mutex, Arc, mpsc::{self, Sender, Receiver}}};
 use serde_json::{json, Value};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use tokio::time;
 use reqwest::Client;
 use base64::engine::general_purpose;
 use base64::Engine;
-use rsa::{RsaPrivateKey, pkcs1::DecodeRsaPrivateKey};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 // Suppress deprecation warnings from underlying cbc/aes crates using old generic_array
 #[allow(deprecated)]
 use cbc::cipher::{KeyIvInit, BlockDecryptMut, generic_array::GenericArray, typenum::U16};
 
-use crate::{cloud_settings::CloudSettings, cloud_subscriber::CloudSubscriber, update_store::UpdateStore, update_descriptor::UpdateDescriptor, crypto::Crypto};
+use crate::{cloud_settings::{CloudSettings, KeyWrapAlgorithm}, cloud_subscriber::CloudSubscriber, update_store::UpdateStore, update_descriptor::UpdateDescriptor, crypto::Crypto, device_id::DeviceId, events::{DaemonEvent, EventBus}, metrics::{Metrics, metrics}};
 
 type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
 
@@ -24,7 +24,7 @@ struct CloudLoginResponse {
     pub iv: String
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
 pub enum UpdateState {
     Dead,
     Disconnected,
@@ -39,43 +39,177 @@ pub enum UpdateState {
     UpdateInProgress
 }
 
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// What the update service is doing, shared with the REST server for diagnostics
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ServiceStatus {
+    pub state: UpdateState,
+    #[serde(rename = "stateChangedAt")]
+    pub state_changed_at: Option<u64>,
+    #[serde(rename = "authFailCount")]
+    pub auth_fail_count: u32,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "lastErrorAt")]
+    pub last_error_at: Option<u64>,
+    #[serde(rename = "brokerConnected")]
+    pub broker_connected: bool,
+    #[serde(rename = "lastMessageAt")]
+    pub last_message_at: Option<u64>,
+    #[serde(skip)]
+    reconnect_requested: bool,
+    #[serde(skip)]
+    heartbeat_at: Option<Instant>,
+}
+
+impl ServiceStatus {
+    pub fn new() -> ServiceStatus {
+        ServiceStatus {
+            state: UpdateState::Dead,
+            state_changed_at: None,
+            auth_fail_count: 0,
+            last_error: None,
+            last_error_at: None,
+            broker_connected: false,
+            last_message_at: None,
+            reconnect_requested: false,
+            heartbeat_at: None,
+        }
+    }
+
+    /// Ask the service to retry authentication; only honored in the `AuthenticationFailed` state
+    pub fn request_reconnect(&mut self) -> Result<(), String> {
+        if self.state != UpdateState::AuthenticationFailed {
+            return Err(format!("Service is {:?}; a reconnect is only possible after authentication has failed", self.state));
+        }
+
+        self.reconnect_requested = true;
+        Ok(())
+    }
+
+    fn take_reconnect_request(&mut self) -> bool {
+        std::mem::take(&mut self.reconnect_requested)
+    }
+
+    pub fn set_state(&mut self, state: UpdateState) {
+        self.state = state;
+        self.state_changed_at = Some(Self::now());
+    }
+
+    pub fn set_error(&mut self, error: impl Into<String>) {
+        self.last_error = Some(error.into());
+        self.last_error_at = Some(Self::now());
+    }
+
+    pub fn set_broker_connected(&mut self, connected: bool) {
+        self.broker_connected = connected;
+    }
+
+    pub fn message_received(&mut self) {
+        self.last_message_at = Some(Self::now());
+    }
+
+    /// Called on every pass of the service loop, so a hung or dead loop shows up on /readyz
+    pub fn heartbeat(&mut self) {
+        self.heartbeat_at = Some(Instant::now());
+    }
+
+    /// Time since the service loop last came round, or None if it never has
+    pub fn since_heartbeat(&self) -> Option<Duration> {
+        self.heartbeat_at.map(|t| t.elapsed())
+    }
+
+    fn now() -> u64 {
+        unix_time()
+    }
+}
+
+impl Default for ServiceStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct UpdateService {
     settings: CloudSettings, 
-    machine_id: String,
+    device_id: DeviceId,
     state: UpdateState,
     store: Arc<Mutex<UpdateStore>>,
+    events: EventBus,
+    status: Arc<Mutex<ServiceStatus>>,
     update_sender: Sender<UpdateDescriptor>,
     update_receiver: Receiver<UpdateDescriptor>,
     state_sender: Sender<UpdateState>,
     state_receiver: Receiver<UpdateState>,
     jwt: String,
     oid: String,
-    auth_fail_count: u32
+    auth_fail_count: u32,
+    downloads_resumed: bool,
+    auth_failed_at: Option<Instant>,
+    token_sender: Option<Sender<String>>,
+    token_refresh_at: Option<Instant>
 }
 
 impl UpdateService {
+    /// Renew the JWT this long before it expires (or halfway through, for short-lived tokens)
+    const TOKEN_REFRESH_MARGIN_SECONDS: u64 = 300;
+    /// Give up on a login (or token refresh) round trip after this long, so the service loop isn't stalled
+    pub(crate) const AUTH_REQUEST_TIMEOUT_SECONDS: u64 = 20;
 
-    pub fn new(settings: CloudSettings, machine_id: String, store: Arc<Mutex<UpdateStore>>) -> UpdateService {
+    pub fn new(settings: CloudSettings, device_id: DeviceId, store: Arc<Mutex<UpdateStore>>) -> UpdateService {
         
         let (update_sender, update_receiver) = mpsc::channel();
         let (state_sender, state_receiver) = mpsc::channel();
+        let events = store.lock()
+            .map(|s| s.events())
+            .unwrap_or_default();
 
         UpdateService {
             settings: settings.clone(), 
-            machine_id: machine_id, 
+            device_id, 
             state: UpdateState::Dead, 
             store,
+            events,
+            status: Arc::new(Mutex::new(ServiceStatus::new())),
             update_sender,
             update_receiver,
             state_sender,
             state_receiver,
             jwt: String::new(),
             oid: String::new(),
-            auth_fail_count: 0
+            auth_fail_count: 0,
+            downloads_resumed: false,
+            auth_failed_at: None,
+            token_sender: None,
+            token_refresh_at: None
         }
     }
 
-    fn _extract_oid_from_jwt(&self, jwt: String) -> Result<String, Box<dyn Error>> {
+    /// Live status of the service, for reporting elsewhere
+    pub fn status(&self) -> Arc<Mutex<ServiceStatus>> {
+        self.status.clone()
+    }
+
+    fn update_status<F: FnOnce(&mut ServiceStatus)>(&self, change: F) {
+        match self.status.lock() {
+            Ok(mut s) => change(&mut s),
+            Err(e) => eprintln!("WARNING: Failed to lock service status: {}", e)
+        }
+    }
+
+    /// When a JWT expires (its `exp` claim, in unix seconds), if it says
+    pub fn token_expiry(jwt: &str) -> Option<u64> {
+        let exp = Self::jwt_claims(jwt).ok()?.get("exp")?.clone();
+        exp.as_u64().or_else(|| exp.as_f64().map(|e| e as u64))
+    }
+
+    fn jwt_claims(jwt: &str) -> Result<Value, Box<dyn Error>> {
         // Split the JWT by '.'
         let parts: Vec<&str> = jwt.split('.').collect();
         
@@ -87,15 +221,22 @@ impl UpdateService {
         // Extract the second part (payload)
         let payload = parts[1];
         
-        // Decode the base64 payload
-        let decoded = general_purpose::STANDARD.decode(payload)?;
+        // Decode the base64 payload (JWTs use the unpadded URL-safe alphabet)
+        let decoded = general_purpose::STANDARD.decode(payload)
+            .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')))?;
         
         // Convert the decoded bytes to a String
         let decoded_str = String::from_utf8(decoded)?;
         
         // Parse the string as JSON
         let json_value: Value = serde_json::from_str(&decoded_str)?;
-        
+
+        Ok(json_value)
+    }
+
+    fn _extract_oid_from_jwt(&self, jwt: String) -> Result<String, Box<dyn Error>> {
+        let json_value = Self::jwt_claims(&jwt)?;
+
         // Extract the "oid" field
         if let Some(oid) = json_value.get("oid") {
             if let Some(oid_str) = oid.as_str() {
@@ -125,26 +266,45 @@ impl UpdateService {
         }
     }
 
+    /// Wait without blocking the LocalSet, so background downloads keep moving, and keep the
+    /// heartbeat fresh so /readyz doesn't mistake a backoff for a hung service
+    async fn pause(&self, seconds: u64) {
+        for _ in 0..seconds {
+            time::sleep(time::Duration::from_secs(1)).await;
+            self.update_status(|s| s.heartbeat());
+        }
+    }
+
+    /// Log in to Meadow.Cloud, counting the attempt
+    async fn authenticate(&mut self) -> Result<(), String> {
+        let result = self._authenticate().await;
+        Metrics::record(&metrics().auth_attempts, result.is_ok());
+        result
+    }
+
     //#[tokio::main] // this doesn't make it 'main' it just makes it synchonous (thanks for clarity, tokio!)
     #[allow(deprecated)]  // Suppress warnings from cbc/aes crates using old generic_array
-    async fn _authenticate(&mut self) -> bool {
+    async fn _authenticate(&mut self) -> Result<(), String> {
         // connect to the cloud and get a JWT
-        let device_id = match fs::read_to_string("/var/lib/dbus/machine-id") {
-            Ok(id) => id.trim().to_ascii_uppercase(),
-            Err(e) => {
-                eprintln!("ERROR: Failed to read machine-id: {}", e);
-                return false;
-            }
+        let device_id = self.device_id.as_str();
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(Self::AUTH_REQUEST_TIMEOUT_SECONDS))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        let endpoint = format!("{}/api/devices/login", self.settings.auth_server_url());
+        let algorithm = self.settings.key_wrap_algorithm;
+        let content = if algorithm == KeyWrapAlgorithm::RsaPkcs1 {
+            json!({
+                "id": device_id
+            })
+        } else {
+            json!({
+                "id": device_id,
+                "keyWrap": algorithm
+            })
         };
 
-        let client = Client::new();
-        let endpoint = format!("{}/api/devices/login", self.settings.auth_server_address
-            .clone()
-            .unwrap_or("https://www.meadowcloud.co".to_string()));
-        let content = json!({
-            "id": device_id
-        });
-
         println!("Log in at {}", endpoint);
 
         match client.post(endpoint)
@@ -156,16 +316,14 @@ impl UpdateService {
 
                     match response.status() {
                         reqwest::StatusCode::NOT_FOUND => {
-                            eprintln!("ID not found.  This device needs to be (re)provisioned.");
                             // TODO: return a state that says "failed and don't retry"
-                            return false;
+                            return Err("ID not found.  This device needs to be (re)provisioned.".to_string());
                         }
                         reqwest::StatusCode::OK => {
                             let json = match response.text().await {
                                 Ok(text) => text,
                                 Err(e) => {
-                                    eprintln!("ERROR: Failed to read response text: {}", e);
-                                    return false;
+                                    return Err(format!("Failed to read response text: {}", e));
                                 }
                             };
 
@@ -175,47 +333,36 @@ impl UpdateService {
                                     let encrypted_key_bytes = match general_purpose::STANDARD.decode(clr.encrypted_key) {
                                         Ok(bytes) => bytes,
                                         Err(e) => {
-                                            eprintln!("ERROR: Failed to decode encrypted key: {}", e);
-                                            return false;
+                                            return Err(format!("Failed to decode encrypted key: {}", e));
                                         }
                                     };
-                                    let private_key_pem = match Crypto::get_private_key_pem(Some(&self.settings.ssh_key_path)) {
-                                        Ok(key) => key,
+                                    let identity = match Crypto::load_identity(Some(self.settings.auth_key_path()), algorithm) {
+                                        Ok(identity) => identity,
                                         Err(e) => {
-                                            eprintln!("ERROR: Failed to get private key: {}", e);
-                                            eprintln!("  Key path: {:?}", self.settings.ssh_key_path);
+                                            eprintln!("  Key path: {:?}", self.settings.auth_key_path());
                                             eprintln!("  You can configure the SSH key path in /etc/meadow.conf using the 'ssh_key_path' setting.");
-                                            eprintln!("Authentication cannot proceed without SSH keys.");
-                                            return false;
+                                            return Err(format!("Failed to get private key: {:#}. Authentication cannot proceed without SSH keys.", e));
                                         }
                                     };
-                                    let key_result = RsaPrivateKey::from_pkcs1_pem(&private_key_pem);
-                                    match key_result {
-                                        Ok(private_key) => {
-                                            let _decrypted_key = match private_key.decrypt(rsa::Pkcs1v15Encrypt, &encrypted_key_bytes) {
-                                                Ok(key) => key,
-                                                Err(e) => {
-                                                    eprintln!("ERROR: Failed to decrypt RSA key: {}", e);
-                                                    return false;
-                                                }
-                                            };
+                                    match identity.unwrap_key(&encrypted_key_bytes) {
+                                        Ok(_decrypted_key) => {
 
                                             // Base64 decode the inputs
                                             let encrypted_token_bytes = match general_purpose::STANDARD.decode(clr.encrypted_token) {
                                                 Ok(bytes) => bytes,
                                                 Err(e) => {
-                                                    eprintln!("ERROR: Failed to decode encrypted token: {}", e);
-                                                    return false;
+                                                    return Err(format!("Failed to decode encrypted token: {}", e));
                                                 }
                                             };
                                             let iv_bytes = match general_purpose::STANDARD.decode(clr.iv) {
                                                 Ok(bytes) => bytes,
                                                 Err(e) => {
-                                                    eprintln!("ERROR: Failed to decode IV: {}", e);
-                                                    return false;
+                                                    return Err(format!("Failed to decode IV: {}", e));
                                                 }
                                             };
-
+                                            if _decrypted_key.len() != 32 || iv_bytes.len() != 16 {
+                                                return Err(format!("Invalid session key or IV length: {} / {}", _decrypted_key.len(), iv_bytes.len()));
+                                            }
 
                                             // Initialize the AES-256 CBC decryptor
                                             let mut decryptor = Aes256CbcDec::new(GenericArray::from_slice(&_decrypted_key), GenericArray::from_slice(&iv_bytes));
@@ -238,8 +385,7 @@ impl UpdateService {
                                             let decrypted_token_bytes = match self._remove_pkcs7_padding(decrypted_buffer) {
                                                 Ok(bytes) => bytes,
                                                 Err(e) => {
-                                                    eprintln!("ERROR: Failed to remove PKCS7 padding: {}", e);
-                                                    return false;
+                                                    return Err(format!("Failed to remove PKCS7 padding: {}", e));
                                                 }
                                             };
 
@@ -247,62 +393,135 @@ impl UpdateService {
                                             self.jwt = match String::from_utf8(decrypted_token_bytes) {
                                                 Ok(s) => s,
                                                 Err(e) => {
-                                                    eprintln!("ERROR: Failed to convert decrypted token to UTF-8: {}", e);
-                                                    return false;
+                                                    return Err(format!("Failed to convert decrypted token to UTF-8: {}", e));
                                                 }
                                             };
                                             self.oid = match self._extract_oid_from_jwt(self.jwt.clone()) {
                                                 Ok(oid) => oid,
                                                 Err(e) => {
-                                                    eprintln!("ERROR: Failed to extract OID from JWT: {}", e);
-                                                    return false;
+                                                    return Err(format!("Failed to extract OID from JWT: {}", e));
                                                 }
                                             };
                                             match self.store.lock() {
                                                 Ok(mut store) => store.set_jwt(self.jwt.clone()),
                                                 Err(e) => {
-                                                    eprintln!("ERROR: Failed to lock store to set JWT: {}", e);
-                                                    return false;
+                                                    return Err(format!("Failed to lock store to set JWT: {}", e));
                                                 }
                                             };
 
-                                            return true;        
+                                            return Ok(());
                                         }
                                         Err(e) => {
-                                            eprintln!("Failed to decrypt private key: {}", e);
-                                            return false;        
+                                            return Err(format!("Failed to decrypt session key ({:?}): {:#}", algorithm, e));
                                         }
                                     }
                                 }
                                 Err(e) => {
-                                    // Print the JSON and the error message in case of failure
-                                    eprintln!("Failed to parse JSON: {}\nOriginal JSON: {}", e, json);
+                                    // Include the JSON with the error message
                                     // TODO: return a state that says "failed and don't retry"
-                                    return false;
+                                    return Err(format!("Failed to parse JSON: {}\nOriginal JSON: {}", e, json));
                                 }
                             }        
                         }                        
                         _=> {
-                            eprintln!("Login call returned a: {}", response.status());
-                            return false;
+                            return Err(format!("Login call returned a: {}", response.status()));
                         }
 
                     }
                 },
                 Err(e) => {
-                    eprintln!("Failed to auth: {}", e);
-                    return false;
+                    return Err(format!("Failed to auth: {}", e));
                 }
             }
     }
 
+    /// Plan the next token renewal from the current JWT's expiry
+    fn schedule_token_refresh(&mut self) {
+        self.token_refresh_at = match Self::token_expiry(&self.jwt) {
+            Some(exp) => {
+                let lifetime = exp.saturating_sub(unix_time());
+                let margin = std::cmp::min(Self::TOKEN_REFRESH_MARGIN_SECONDS, lifetime / 2);
+                println!("Token expires in {} seconds; refreshing in {}", lifetime, lifetime - margin);
+                Some(Instant::now() + Duration::from_secs(lifetime - margin))
+            },
+            None => {
+                println!("WARNING: Token has no expiry; it will only be refreshed if a download is refused");
+                None
+            }
+        };
+    }
+
+    /// Whether the token is about to expire, or a download was refused with the current one
+    fn token_refresh_due(&self) -> bool {
+        let expiring = self.token_refresh_at.is_some_and(|t| Instant::now() >= t);
+        let rejected = match self.store.lock() {
+            Ok(store) => store.take_reauth_request(),
+            Err(_) => false
+        };
+        expiring || rejected
+    }
+
+    /// Re-authenticate and hand the new token to the store and the MQTT subscriber
+    async fn refresh_token(&mut self) {
+        println!("Refreshing authentication token...");
+
+        match self.authenticate().await {
+            Ok(()) => {
+                self.schedule_token_refresh();
+                if let Some(sender) = &self.token_sender
+                    && let Err(e) = sender.send(self.jwt.clone()) {
+                    eprintln!("WARNING: Failed to pass refreshed token to the MQTT subscriber: {}", e);
+                }
+            },
+            Err(e) => {
+                eprintln!("ERROR: Token refresh failed: {}", e);
+                self.update_status(|s| s.set_error(format!("Token refresh failed: {}", e)));
+                self.token_refresh_at = Some(Instant::now() + Duration::from_secs(self.settings.connect_retry_seconds));
+            }
+        }
+    }
+
+    /// Start a download in the background so the state machine keeps running
+    fn spawn_download(store: &UpdateStore, update_id: &String) {
+        match store.begin_download(update_id) {
+            Ok(job) => {
+                tokio::task::spawn_local(async move {
+                    let id = job.update_id().to_string();
+                    match job.run().await {
+                        Ok(size) => println!("Download of {} completed: {} bytes", id, size),
+                        Err(e) => eprintln!("WARNING: Download of {} failed: {}. Update can be downloaded manually via REST API.", id, e)
+                    }
+                });
+            },
+            Err(e) => {
+                eprintln!("WARNING: Auto-download failed: {}. Update can be downloaded manually via REST API.", e);
+            }
+        }
+    }
+
+    /// Finish any downloads that were cut short, e.g. by a daemon restart
+    fn resume_partial_downloads(&self) {
+        match self.store.lock() {
+            Ok(store) => {
+                for update_id in store.get_partial_downloads() {
+                    println!("Resuming interrupted download: {}", update_id);
+                    Self::spawn_download(&store, &update_id);
+                }
+            },
+            Err(e) => {
+                eprintln!("ERROR: Failed to lock store to resume downloads: {}", e);
+            }
+        }
+    }
+
     pub async fn start(&mut self) {
 
         let subscriber = Arc::new(Mutex::new(
             CloudSubscriber::new(
                 self.settings.clone(), 
-                self.machine_id.to_ascii_uppercase().clone(),
-                String::new()
+                self.device_id.clone(),
+                String::new(),
+                self.status.clone()
                 )));
         
 //        sleep(time::Duration::from_secs(self.settings.connect_retry_seconds));
@@ -311,11 +530,21 @@ impl UpdateService {
         let mut last_state = self.state;
 
         loop {
+            self.update_status(|s| s.heartbeat());
             let current_state = self.state;
 
             if last_state != current_state {
                 println!("service state: {:?}", current_state);
                 last_state = current_state;
+                self.update_status(|s| s.set_state(current_state));
+                self.events.publish(DaemonEvent::StateChanged { state: current_state });
+            }
+
+            // keep the token fresh while connected
+            if self.settings.use_authentication
+                && matches!(current_state, UpdateState::Connected | UpdateState::Idle)
+                && self.token_refresh_due() {
+                self.refresh_token().await;
             }
 
             match current_state {
@@ -331,50 +560,98 @@ impl UpdateService {
                     }
                 }
                 UpdateState::Authenticating => {
-                    if self._authenticate().await {
-                        self.state = UpdateState::Authenticated;
-                        self.auth_fail_count = 0;
-                    }
-                    else {
-                        self.auth_fail_count += 1;
-
-                        // Check if we've exceeded max retries
-                        if self.auth_fail_count >= self.settings.auth_max_retries {
-                            eprintln!("ERROR: Authentication failed after {} attempts. Moving to AuthenticationFailed state.", self.auth_fail_count);
-                            eprintln!("This device may need to be provisioned. Check that the device ID is registered in Meadow.Cloud.");
-                            self.state = UpdateState::AuthenticationFailed;
-                        } else {
-                            // Exponential backoff with cap at 60 seconds
-                            let backoff_seconds = std::cmp::min(self.auth_fail_count * 5, 60);
-                            println!("Authentication attempt {}/{} failed. Retrying in {} seconds...",
-                                self.auth_fail_count, self.settings.auth_max_retries, backoff_seconds);
-                            thread::sleep(Duration::from_secs(u64::from(backoff_seconds)));
+                    match self.authenticate().await {
+                        Ok(()) => {
+                            self.schedule_token_refresh();
+                            self.state = UpdateState::Authenticated;
+                            self.auth_fail_count = 0;
+                            self.update_status(|s| s.auth_fail_count = 0);
+                        },
+                        Err(e) => {
+                            eprintln!("ERROR: Authentication failed: {}", e);
+                            self.auth_fail_count += 1;
+                            let fail_count = self.auth_fail_count;
+                            self.update_status(|s| {
+                                s.auth_fail_count = fail_count;
+                                s.set_error(format!("Authentication failed: {}", e));
+                            });
+
+                            // Check if we've exceeded max retries
+                            if self.auth_fail_count >= self.settings.auth_max_retries {
+                                eprintln!("ERROR: Authentication failed after {} attempts. Moving to AuthenticationFailed state.", self.auth_fail_count);
+                                eprintln!("This device may need to be provisioned. Check that the device ID is registered in Meadow.Cloud.");
+                                self.state = UpdateState::AuthenticationFailed;
+                            } else {
+                                // Exponential backoff with cap at 60 seconds
+                                let backoff_seconds = std::cmp::min(self.auth_fail_count * 5, 60);
+                                println!("Authentication attempt {}/{} failed. Retrying in {} seconds...",
+                                    self.auth_fail_count, self.settings.auth_max_retries, backoff_seconds);
+                                self.pause(u64::from(backoff_seconds)).await;
+                            }
                         }
                     }
                 },
                 UpdateState::AuthenticationFailed => {
-                    // Stay in this state until manual intervention
-                    // The REST API or service restart can reset this
-                    println!("Service is in AuthenticationFailed state. Manual intervention required.");
-                    println!("Possible actions:");
-                    println!("  1. Verify device is provisioned in Meadow.Cloud");
-                    println!("  2. Check SSH keys are present and valid");
-                    println!("  3. Restart the daemon to retry authentication");
-                    thread::sleep(Duration::from_secs(60)); // Check once per minute
+                    // Stay in this state until a reconnect is requested over REST
+                    // or, if configured, the slow retry interval has passed
+                    let failed_since = match self.auth_failed_at {
+                        Some(t) => t,
+                        None => {
+                            println!("Service is in AuthenticationFailed state. Manual intervention required.");
+                            println!("Possible actions:");
+                            println!("  1. Verify device is provisioned in Meadow.Cloud");
+                            println!("  2. Check SSH keys are present and valid");
+                            println!("  3. Call POST /api/cloud/reconnect (or restart the daemon) to retry authentication");
+                            let now = Instant::now();
+                            self.auth_failed_at = Some(now);
+                            now
+                        }
+                    };
+
+                    let reconnect_requested = match self.status.lock() {
+                        Ok(mut s) => s.take_reconnect_request(),
+                        Err(_) => false
+                    };
+                    let retry_due = self.settings.auth_retry_minutes > 0
+                        && failed_since.elapsed() >= Duration::from_secs(self.settings.auth_retry_minutes * 60);
+
+                    if reconnect_requested {
+                        println!("Reconnect requested. Retrying authentication...");
+                        self.auth_fail_count = 0;
+                        self.update_status(|s| s.auth_fail_count = 0);
+                        self.auth_failed_at = None;
+                        self.state = UpdateState::Authenticating;
+                    }
+                    else if retry_due {
+                        println!("Retrying authentication after {} minutes...", self.settings.auth_retry_minutes);
+                        // a single attempt; another failure comes straight back here
+                        self.auth_fail_count = self.settings.auth_max_retries.saturating_sub(1);
+                        self.auth_failed_at = None;
+                        self.state = UpdateState::Authenticating;
+                    }
                 },
                 UpdateState::Authenticated => {
+                    // a download cut short by a restart carries on as soon as there's a token for it,
+                    // whether or not new updates are downloaded automatically
+                    if !self.downloads_resumed {
+                        self.downloads_resumed = true;
+                        self.resume_partial_downloads();
+                    }
+
                     let s = subscriber.clone();
                     let upd_snd = self.update_sender.clone();
                     let st_snd = self.state_sender.clone();
                     let jwt_copy = self.jwt.clone();
                     let oid_copy = self.oid.clone();
+                    let (token_sender, token_receiver) = mpsc::channel();
+                    self.token_sender = Some(token_sender);
 
                     // this spawns a cloud MQTT listener/subscriber.
                     // when it connects, it will update the state to connected
                     thread::spawn(move || {
                         match s.lock() {
                             Ok(mut subscriber) => {
-                                subscriber.start(upd_snd, st_snd, jwt_copy, oid_copy);
+                                subscriber.start(upd_snd, st_snd, jwt_copy, oid_copy, token_receiver);
                             },
                             Err(e) => {
                                 eprintln!("ERROR: Failed to lock subscriber: {}", e);
@@ -401,10 +678,7 @@ impl UpdateService {
                                     // Auto-download if enabled
                                     if self.settings.auto_download_updates {
                                         println!("Auto-downloading update: {}", update_id);
-                                        match store.retrieve_update(&update_id).await {
-                                            Ok(size) => println!("Auto-download completed: {} bytes", size),
-                                            Err(e) => eprintln!("WARNING: Auto-download failed: {}. Update can be downloaded manually via REST API.", e)
-                                        }
+                                        Self::spawn_download(&store, &update_id);
                                     }
 
                                     // Transition to Idle state - updates are available in the store
@@ -439,10 +713,7 @@ impl UpdateService {
                                     // Auto-download if enabled
                                     if self.settings.auto_download_updates {
                                         println!("Auto-downloading update: {}", update_id);
-                                        match store.retrieve_update(&update_id).await {
-                                            Ok(size) => println!("Auto-download completed: {} bytes", size),
-                                            Err(e) => eprintln!("WARNING: Auto-download failed: {}. Update can be downloaded manually via REST API.", e)
-                                        }
+                                        Self::spawn_download(&store, &update_id);
                                     }
                                 },
                                 Err(e) => {
@@ -456,13 +727,8 @@ impl UpdateService {
                     // and are handled by the UpdateStore directly, not through state machine
                 },
                 UpdateState::DownloadingFile => {
-                    // This state is set by REST API when a download is initiated
-                    // The actual download happens in UpdateStore::retrieve_update()
-                    // State transitions back to Idle when download completes or fails
-
-                    // For now, this is a placeholder - downloads are synchronous in REST handlers
-                    // Future enhancement: make downloads async and track progress here
-                    println!("DownloadingFile state - download in progress via REST API");
+                    // Downloads run as background jobs (see UpdateStore::begin_download) and
+                    // report their progress through the store, so the service never sits here
                 },
                 UpdateState::UpdateInProgress => {
                     // This state is set by REST API when an update application is initiated
@@ -484,7 +750,8 @@ impl UpdateService {
                 _ => { /* NOP */ }
             }
 
-            sleep(time::Duration::from_secs(1));
+            // yield (rather than block) so background downloads keep moving
+            time::sleep(time::Duration::from_secs(1)).await;
         }
     }
 }
\ No newline at end of file
@@ -1,29 +1,74 @@
 use std::ffi::OsStr;
 use std::sync::{Mutex, Arc};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread::{self, sleep};
-use std::time::Duration;
-use std::{collections::{HashMap, HashSet}, ops::Deref};
-use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
 use std::fs::{self, OpenOptions, File};
-use std::io::{Write, Cursor, copy, BufReader};
+use std::io::{Write, Read, Seek, SeekFrom, BufReader};
 use zip::ZipArchive;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
+#[cfg(unix)]
+use std::os::unix::fs::{lchown, symlink, MetadataExt, PermissionsExt};
+
+use crate::{cloud_settings::{CloudSettings, HealthCheckMode}, events::{DaemonEvent, EventBus}, release_store::{Release, ReleaseStore}, package_verifier::PackageVerifier, update_descriptor::{UpdateDescriptor, UpdateStatus}, update_error::UpdateError, metrics::{ApplyTimer, Metrics, metrics}};
+
+/// Transfer progress for a download that is underway
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DownloadProgress {
+    #[serde(rename = "bytesReceived")]
+    pub bytes_received: u64,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: Option<u64>,
+}
 
-use crate::{cloud_settings::CloudSettings, update_descriptor::UpdateDescriptor};
+/// Why a download attempt stopped
+enum DownloadFailure {
+    /// Transient problem (network drop, server error); the partial file is kept for a resume
+    Retry(String),
+    /// The server rejected our token; worth another go once it has been refreshed
+    Unauthorized(String),
+    /// Nothing to gain by trying again
+    Fatal(String)
+}
 
 pub struct UpdateStore {
     _settings: CloudSettings,
     store_root_folder: PathBuf,
     store_directory: PathBuf,
     updates: HashMap<String, Arc<Mutex<UpdateDescriptor>>>,
-    jwt: String
+    downloads: Arc<Mutex<HashMap<String, DownloadProgress>>>,
+    update_confirmed: Arc<AtomicBool>,
+    events: EventBus,
+    jwt: Arc<Mutex<String>>,
+    reauth_requested: Arc<AtomicBool>
 }
 
 impl UpdateStore {
     const UPDATE_INFO_FILE_NAME: &'static str = "info.json";
+    const PACKAGE_FILE_NAME: &'static str = "update.mpak";
+    const PARTIAL_FILE_NAME: &'static str = "update.mpak.partial";
+    const SIGNATURE_FILE_NAME: &'static str = "update.mpak.sig";
+    const APPLIED_MARKER_FILE_NAME: &'static str = "applied";
+    const DOWNLOAD_CHECKPOINT_BYTES: u64 = 8 * 1024 * 1024;
+    const DOWNLOAD_PROGRESS_EVENT_BYTES: u64 = 256 * 1024;
+    const TOKEN_REFRESH_WAIT_SECONDS: u64 = 60;
+
+    // unix file type bits, as stored in a zip entry's external attributes
+    const S_IFMT: u32 = 0o170000;
+    const S_IFREG: u32 = 0o100000;
+    const S_IFDIR: u32 = 0o040000;
+    const S_IFLNK: u32 = 0o120000;
+    // permission bits honored from a package (no setuid, setgid or sticky)
+    const PACKAGE_MODE_MASK: u32 = 0o777;
+    // how many package symlinks one target may pass through before it counts as a loop
+    const MAX_SYMLINK_DEPTH: usize = 40;
 
     pub fn new(settings: CloudSettings) -> UpdateStore {
         let store_root = settings.update_store_path.clone();
@@ -33,7 +78,11 @@ impl UpdateStore {
             store_root_folder: store_root.clone(),
             store_directory: store_root,
             updates: HashMap::new(),
-            jwt: String::new()
+            downloads: Arc::new(Mutex::new(HashMap::new())),
+            update_confirmed: Arc::new(AtomicBool::new(false)),
+            events: EventBus::new(),
+            jwt: Arc::new(Mutex::new(String::new())),
+            reauth_requested: Arc::new(AtomicBool::new(false))
         };
         
         println!("Update data will be stored in '{:?}'", store.store_directory);
@@ -65,9 +114,10 @@ impl UpdateStore {
                                             match File::open(fp) {
                                                 Ok(file) => {
                                                     let reader = BufReader::new(file);
-                                                    match serde_json::from_reader(reader) {
-                                                        Ok(descriptor) => {
+                                                    match serde_json::from_reader::<_, UpdateDescriptor>(reader) {
+                                                        Ok(mut descriptor) => {
                                                             // TODO: verify the mpak existence for "retrieved" items?
+                                                            descriptor.recover_status();
                                                             store.add(Arc::new(descriptor))
                                                         },
                                                         Err(err) => {
@@ -113,10 +163,20 @@ impl UpdateStore {
     }
 
     pub fn add(&mut self, descriptor: Arc<UpdateDescriptor>) {
-        let rf = Arc::new( Mutex::new((*descriptor).clone()));
-        let id = descriptor.deref().mpak_id.clone();
-        self.updates.insert(id, rf);
-        self.save_or_update(descriptor.deref());
+        let mut d = (*descriptor).clone();
+        if d.status_changed_at.is_none() {
+            // stamp when we first heard about it
+            d.set_status(d.status);
+        }
+        let id = d.mpak_id.clone();
+        Self::write_descriptor(&self.store_root_folder, &d);
+        self.events.publish(DaemonEvent::UpdateAdded { update: d.clone() });
+        self.updates.insert(id, Arc::new(Mutex::new(d)));
+    }
+
+    /// The bus that store and service changes are published on
+    pub fn events(&self) -> EventBus {
+        self.events.clone()
     }
 
     pub fn len(&self) -> i32 {
@@ -221,13 +281,13 @@ impl UpdateStore {
                     match entry {
                         Ok(e) => {
                             let path = e.path();
-                            if path.is_file() {
-                                // Store relative path from base_dir
-                                if let Ok(rel_path) = path.strip_prefix(base_dir) {
-                                    files.insert(rel_path.to_path_buf());
-                                }
-                            } else if path.is_dir() {
+                            // symlinks count as files; following one could walk outside the package
+                            let is_dir = fs::symlink_metadata(&path).map(|m| m.is_dir()).unwrap_or(false);
+                            if is_dir {
                                 Self::collect_files_recursive(base_dir, &path, files)?;
+                            } else if let Ok(rel_path) = path.strip_prefix(base_dir) {
+                                // Store relative path from base_dir
+                                files.insert(rel_path.to_path_buf());
                             }
                         }
                         Err(e) => {
@@ -268,27 +328,41 @@ impl UpdateStore {
                                 Err(_) => continue,
                             };
 
-                            if path.is_file() {
-                                // Only copy if NOT in new_files set
-                                if !new_files.contains(rel_path) {
-                                    let dest_path = dest_base.join(rel_path);
+                            let metadata = match fs::symlink_metadata(&path) {
+                                Ok(m) => m,
+                                Err(e) => return Err(format!("Failed to read metadata for {:?}: {}", path, e)),
+                            };
 
-                                    // Create parent directory if needed
-                                    if let Some(parent) = dest_path.parent() {
-                                        if let Err(e) = fs::create_dir_all(parent) {
-                                            return Err(format!("Failed to create directory {:?}: {}", parent, e));
-                                        }
+                            if metadata.is_dir() {
+                                let dest_dir = dest_base.join(rel_path);
+                                match fs::symlink_metadata(&dest_dir) {
+                                    Ok(existing) if !existing.is_dir() => {
+                                        // the package replaced this directory with a file or symlink
+                                        continue;
                                     }
-
-                                    // Copy the file
-                                    if let Err(e) = fs::copy(&path, &dest_path) {
-                                        return Err(format!("Failed to copy {:?} to {:?}: {}", path, dest_path, e));
+                                    Ok(_) => {}
+                                    Err(_) => {
+                                        if let Err(e) = fs::create_dir_all(&dest_dir) {
+                                            return Err(format!("Failed to create directory {:?}: {}", dest_dir, e));
+                                        }
+                                        Self::copy_metadata(&metadata, &dest_dir, None)?;
                                     }
-
-                                    count += 1;
                                 }
-                            } else if path.is_dir() {
                                 count += Self::merge_files_recursive(base_dir, &path, dest_base, new_files)?;
+                            } else if !new_files.contains(rel_path) {
+                                // Only copy if NOT in new_files set
+                                let dest_path = dest_base.join(rel_path);
+
+                                // Create parent directory if needed
+                                if let Some(parent) = dest_path.parent()
+                                    && let Err(e) = fs::create_dir_all(parent) {
+                                    return Err(format!("Failed to create directory {:?}: {}", parent, e));
+                                }
+
+                                // Copy the file (or symlink) keeping its mode and owner
+                                Self::copy_entry(&path, &metadata, &dest_path, None)?;
+
+                                count += 1;
                             }
                         }
                         Err(e) => {
@@ -302,6 +376,96 @@ impl UpdateStore {
         }
     }
 
+    /// Copy the contents of `source` into `dest`, keeping file modes, ownership and symlinks
+    ///
+    /// When `owner` is given everything copied is given that uid/gid, otherwise the source
+    /// ownership is kept.  Ownership changes only take effect when the daemon runs as root.
+    pub(crate) fn copy_tree(source: &Path, dest: &Path, owner: Option<(u32, u32)>) -> Result<usize, String> {
+        if let Err(e) = fs::create_dir_all(dest) {
+            return Err(format!("Failed to create directory {:?}: {}", dest, e));
+        }
+
+        let entries = match fs::read_dir(source) {
+            Ok(entries) => entries,
+            Err(e) => return Err(format!("Failed to read directory {:?}: {}", source, e)),
+        };
+
+        let mut count = 0;
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => return Err(format!("Failed to read directory entry in {:?}: {}", source, e)),
+            };
+
+            let path = entry.path();
+            let target = dest.join(entry.file_name());
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(m) => m,
+                Err(e) => return Err(format!("Failed to read metadata for {:?}: {}", path, e)),
+            };
+
+            if metadata.is_dir() {
+                count += Self::copy_tree(&path, &target, owner)?;
+                Self::copy_metadata(&metadata, &target, owner)?;
+            } else {
+                Self::copy_entry(&path, &metadata, &target, owner)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Copy a single file or symlink, replacing whatever is at `dest`
+    fn copy_entry(source: &Path, metadata: &fs::Metadata, dest: &Path, owner: Option<(u32, u32)>) -> Result<(), String> {
+        // fs::copy would write through an existing symlink, so clear the way first
+        if let Ok(existing) = fs::symlink_metadata(dest) {
+            let removed = if existing.is_dir() {
+                fs::remove_dir_all(dest)
+            } else {
+                fs::remove_file(dest)
+            };
+            if let Err(e) = removed {
+                return Err(format!("Failed to replace {:?}: {}", dest, e));
+            }
+        }
+
+        if metadata.file_type().is_symlink() {
+            let link_target = match fs::read_link(source) {
+                Ok(t) => t,
+                Err(e) => return Err(format!("Failed to read symlink {:?}: {}", source, e)),
+            };
+            if let Err(e) = symlink(&link_target, dest) {
+                return Err(format!("Failed to create symlink {:?} -> {:?}: {}", dest, link_target, e));
+            }
+        } else if let Err(e) = fs::copy(source, dest) {
+            return Err(format!("Failed to copy {:?} to {:?}: {}", source, dest, e));
+        }
+
+        Self::copy_metadata(metadata, dest, owner)
+    }
+
+    /// Apply the ownership and mode from `metadata` to `dest` without following symlinks
+    fn copy_metadata(metadata: &fs::Metadata, dest: &Path, owner: Option<(u32, u32)>) -> Result<(), String> {
+        let (uid, gid) = owner.unwrap_or((metadata.uid(), metadata.gid()));
+        match lchown(dest, Some(uid), Some(gid)) {
+            Ok(_) => {}
+            // only root can give files away; an unprivileged daemon keeps its own ownership
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {}
+            Err(e) => return Err(format!("Failed to set owner of {:?}: {}", dest, e)),
+        }
+
+        // chown clears setuid/setgid, so the mode goes on last; symlinks have no mode of their own
+        if !metadata.file_type().is_symlink() {
+            let mode = fs::Permissions::from_mode(metadata.mode() & 0o7777);
+            if let Err(e) = fs::set_permissions(dest, mode) {
+                return Err(format!("Failed to set permissions of {:?}: {}", dest, e));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Try atomic swap first, fallback to file-by-file if cross-device error
     ///
     /// This provides optimal performance when possible (atomic rename on same filesystem)
@@ -312,13 +476,16 @@ impl UpdateStore {
         match Self::atomic_directory_swap(current, staging, rollback) {
             Ok(_) => {
                 println!("✓ Atomic swap succeeded");
+                metrics().swaps.with_label_values(&["atomic"]).inc();
                 Ok(())
             }
             Err(e) => {
                 // Check if it's a cross-device error
                 if e.contains("cross-device") || e.contains("Invalid cross-device link") {
                     println!("⚠ Cross-device link detected, using file-by-file fallback");
-                    Self::file_by_file_swap(current, staging, rollback)
+                    Self::file_by_file_swap(current, staging, rollback)?;
+                    metrics().swaps.with_label_values(&["file_by_file"]).inc();
+                    Ok(())
                 } else {
                     // Other error, propagate it
                     Err(e)
@@ -353,11 +520,7 @@ impl UpdateStore {
         println!("  Copy: {:?} -> {:?}", current, rollback);
 
         // Copy current version to rollback
-        let opts = fs_extra::dir::CopyOptions::new()
-            .overwrite(true)
-            .content_only(true);
-
-        if let Err(e) = fs_extra::dir::copy(current, rollback, &opts) {
+        if let Err(e) = Self::copy_tree(current, rollback, None) {
             return Err(format!("Failed to backup current version to rollback: {}", e));
         }
 
@@ -368,7 +531,9 @@ impl UpdateStore {
                 for entry in entries {
                     if let Ok(entry) = entry {
                         let path = entry.path();
-                        if path.is_dir() {
+                        // don't follow symlinks; removing through one would delete the target
+                        let is_dir = fs::symlink_metadata(&path).map(|m| m.is_dir()).unwrap_or(false);
+                        if is_dir {
                             if let Err(e) = fs::remove_dir_all(&path) {
                                 return Err(format!("Failed to remove directory {:?}: {}", path, e));
                             }
@@ -389,12 +554,12 @@ impl UpdateStore {
         println!("  Copy: {:?} -> {:?}", staging, current);
 
         // Copy staging to current
-        if let Err(e) = fs_extra::dir::copy(staging, current, &opts) {
+        if let Err(e) = Self::copy_tree(staging, current, None) {
             // CRITICAL ERROR: Try to restore from rollback
             eprintln!("ERROR: Failed to deploy new version: {}", e);
             eprintln!("Attempting to restore from rollback...");
 
-            if let Err(restore_err) = fs_extra::dir::copy(rollback, current, &opts) {
+            if let Err(restore_err) = Self::copy_tree(rollback, current, None) {
                 return Err(format!(
                     "CRITICAL: Failed to deploy new version AND failed to restore from rollback! \
                     Original error: {}. Restore error: {}. \
@@ -466,31 +631,383 @@ impl UpdateStore {
         Ok(())
     }
 
-    pub async fn apply_update(&self, id: &String, app_path: &PathBuf, pid: i32, command: &Option<String>) -> Result<u64, String> {
+    /// Start the app after an update, either through systemd or as a detached process
+    ///
+    /// Returns the process handle when the daemon spawned the app itself.
+    fn start_app(settings: &CloudSettings, executable: &Path, working_dir: &Path, command: &Option<String>) -> Option<Child> {
+        if settings.app_is_systemd_service {
+            // Restart via systemd
+            if let Some(ref service_name) = settings.app_service_name {
+                println!("Starting systemd service '{}'...", service_name);
+                match Command::new("systemctl")
+                    .arg("start")
+                    .arg(service_name)
+                    .output() {
+                    Ok(output) => {
+                        if output.status.success() {
+                            println!("Successfully started service '{}'", service_name);
+                        } else {
+                            eprintln!("ERROR: Failed to start service '{}': {}",
+                                service_name,
+                                String::from_utf8_lossy(&output.stderr));
+                        }
+                    },
+                    Err(e) => {
+                        eprintln!("ERROR: Failed to execute systemctl start: {}", e);
+                    }
+                }
+            } else {
+                eprintln!("ERROR: app_is_systemd_service is true but app_service_name is not set!");
+            }
+            return None;
+        }
+
+        // Direct process spawn
+        println!("Launching '{:?}' in directory '{:?}'...", executable, working_dir);
+        let mut cmd = match command {
+            None => Command::new(executable),
+            Some(c) => {
+                let mut cmd = Command::new(c);
+                cmd.arg(executable);
+                cmd
+            }
+        };
+
+        match cmd
+            .current_dir(working_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn() {
+            Ok(child) => Some(child),
+            Err(e) => {
+                eprintln!("ERROR: Failed to start process: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Decide whether the restarted app is healthy, as configured by `health_check_mode`
+    fn check_app_health(settings: &CloudSettings, app: &mut Option<Child>, confirmed: &AtomicBool) -> Result<(), String> {
+        let mode = settings.health_check_mode;
+        if mode == HealthCheckMode::None {
+            return Ok(());
+        }
+
+        println!("Running {:?} health check for up to {} seconds...", mode, settings.health_check_seconds);
+
+        // the probe is a one-off async request from this plain thread
+        let probe = match (mode, &settings.health_check_url) {
+            (HealthCheckMode::Http, Some(url)) => {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|e| format!("Failed to create runtime for health probe: {}", e))?;
+                Some((runtime, reqwest::Client::new(), url.clone()))
+            },
+            (HealthCheckMode::Http, None) => {
+                return Err("health_check_mode is http but health_check_url is not set".to_string());
+            },
+            _ => None,
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(settings.health_check_seconds);
+        loop {
+            Self::check_app_running(settings, app)?;
+
+            let healthy = match (&probe, mode) {
+                (Some((runtime, client, url)), _) => runtime.block_on(async {
+                    client.get(url).timeout(Duration::from_secs(2)).send().await
+                        .map(|r| r.status().is_success())
+                        .unwrap_or(false)
+                }),
+                (None, HealthCheckMode::Confirm) => confirmed.load(Ordering::SeqCst),
+                _ => false,
+            };
+            if healthy {
+                println!("Health check passed");
+                return Ok(());
+            }
+
+            if Instant::now() >= deadline {
+                return match mode {
+                    HealthCheckMode::Process => {
+                        println!("Health check passed");
+                        Ok(())
+                    },
+                    HealthCheckMode::Confirm => Err(format!("App did not confirm the update within {} seconds", settings.health_check_seconds)),
+                    _ => Err(format!("Health probe did not succeed within {} seconds", settings.health_check_seconds)),
+                };
+            }
+
+            sleep(Duration::from_millis(1000));
+        }
+    }
+
+    /// Check that the app (or its systemd service) is still running
+    fn check_app_running(settings: &CloudSettings, app: &mut Option<Child>) -> Result<(), String> {
+        if settings.app_is_systemd_service {
+            let service_name = settings.app_service_name.as_ref()
+                .ok_or_else(|| "app_is_systemd_service is true but app_service_name is not set".to_string())?;
+            let active = Command::new("systemctl")
+                .arg("is-active")
+                .arg("--quiet")
+                .arg(service_name)
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false);
+            if !active {
+                return Err(format!("Service '{}' is not running", service_name));
+            }
+            return Ok(());
+        }
+
+        match app {
+            Some(child) => match child.try_wait() {
+                Ok(None) => Ok(()),
+                Ok(Some(status)) => Err(format!("App exited during health check ({})", status)),
+                Err(e) => Err(format!("Failed to check app process: {}", e)),
+            },
+            None => Err("App could not be started".to_string()),
+        }
+    }
+
+    /// Swap the previous version back in from `rollback_path` and start it
+    fn roll_back_app(settings: &CloudSettings, app: Option<Child>, app_dir: &Path, executable: &Path, command: &Option<String>) -> Result<(), String> {
+        // stop whatever is left of the new version
+        if settings.app_is_systemd_service {
+            Self::stop_app_service(settings);
+        } else if let Some(mut child) = app {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+
+        Self::restore_previous_version(settings, app_dir)?;
+        metrics().rollbacks.with_label_values(&["health_check"]).inc();
+
+        // nothing to check here; if the old version won't start either there is nothing left to try
+        let _ = Self::start_app(settings, executable, app_dir, command);
+
+        Ok(())
+    }
+
+    fn stop_app_service(settings: &CloudSettings) {
+        if let Some(ref service_name) = settings.app_service_name {
+            println!("Stopping systemd service '{}'...", service_name);
+            match Command::new("systemctl").arg("stop").arg(service_name).output() {
+                Ok(output) if !output.status.success() => {
+                    eprintln!("WARNING: Failed to stop service '{}': {}",
+                        service_name,
+                        String::from_utf8_lossy(&output.stderr));
+                },
+                Ok(_) => {},
+                Err(e) => {
+                    eprintln!("WARNING: Failed to execute systemctl stop: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Put the previous version back in place, discarding the current version
+    ///
+    /// With `keep_releases` this is the release installed before the current one, otherwise
+    /// the copy in `rollback_path` is swapped back into `app_dir`.
+    fn restore_previous_version(settings: &CloudSettings, app_dir: &Path) -> Result<(), String> {
+        if settings.keep_releases > 0 {
+            let release_id = ReleaseStore::new(settings).roll_back()?;
+            println!("Rolled back to release '{}'", release_id);
+            return Ok(());
+        }
+
+        println!("ROLLING BACK to previous version from {:?}", settings.rollback_path);
+
+        if !settings.rollback_path.is_dir() {
+            return Err(format!("No previous version to roll back to at {:?}", settings.rollback_path));
+        }
+
+        // the staging area is free between updates; the discarded version is parked there
+        let discarded_dir = &settings.staging_path;
+        if discarded_dir.exists() {
+            let _ = fs::remove_dir_all(discarded_dir);
+        }
+        Self::swap_with_fallback(app_dir, &settings.rollback_path, discarded_dir)?;
+        let _ = fs::remove_dir_all(discarded_dir);
+
+        println!("Previous version restored to {:?}", app_dir);
+        Ok(())
+    }
+
+    /// Make a staged app the live version, keeping the one it replaces for a rollback
+    fn install_staged_app(settings: &CloudSettings, app_dir: &Path, staging: &Path, release_id: &str) -> Result<(), String> {
+        if settings.keep_releases > 0 {
+            ReleaseStore::new(settings).install(app_dir, staging, release_id)?;
+            metrics().swaps.with_label_values(&["release"]).inc();
+            Ok(())
+        } else {
+            Self::swap_with_fallback(app_dir, staging, &settings.rollback_path)
+        }
+    }
+
+    /// Retained releases, oldest first (empty unless `keep_releases` is set)
+    pub fn list_releases(&self) -> Vec<Release> {
+        if self._settings.keep_releases == 0 {
+            return Vec::new();
+        }
+        ReleaseStore::new(&self._settings).list()
+    }
+
+    /// Roll the app back to the previous version
+    ///
+    /// The app is stopped (via systemd, or by sending SIGTERM to `pid`), the previous version
+    /// is put back and restarted, and the most recently applied update is marked as rolled
+    /// back.  Runs in the background; returns the id of the update being rolled back.
+    pub fn rollback_update(&self, app_dir: &Path, executable_path: &Path, pid: Option<i32>, command: &Option<String>) -> Result<Option<String>, UpdateError> {
+        println!("ROLLBACK REQUESTED");
+
+        let settings = &self._settings;
+        if settings.keep_releases > 0 {
+            if ReleaseStore::new(settings).previous().is_none() {
+                return Err(UpdateError::NoPreviousVersion("No previous release to roll back to".to_string()));
+            }
+        } else if !settings.rollback_path.is_dir() {
+            return Err(UpdateError::NoPreviousVersion(format!("No previous version to roll back to at {:?}", settings.rollback_path)));
+        }
+
+        self.restart_app_version(app_dir, executable_path, pid, command, None)
+    }
+
+    /// Stop the app, switch to a retained release and start it again (in the background)
+    pub fn switch_release(&self, release_id: &str, app_dir: &Path, executable_path: &Path, pid: Option<i32>, command: &Option<String>) -> Result<Option<String>, UpdateError> {
+        println!("SWITCH TO RELEASE {} REQUESTED", release_id);
+
+        if self._settings.keep_releases == 0 {
+            return Err(UpdateError::ReleasesDisabled);
+        }
+        if !self.list_releases().iter().any(|r| r.id == release_id) {
+            return Err(UpdateError::ReleaseNotFound(release_id.to_string()));
+        }
+
+        self.restart_app_version(app_dir, executable_path, pid, command, Some(release_id.to_string()))
+    }
+
+    /// Stop the app, change the installed version and restart it
+    ///
+    /// `release_id` picks a retained release; None rolls back to the previous version.
+    fn restart_app_version(&self, app_dir: &Path, executable_path: &Path, pid: Option<i32>, command: &Option<String>, release_id: Option<String>) -> Result<Option<String>, UpdateError> {
+        let applied_id = fs::read_to_string(self.store_root_folder.join(Self::APPLIED_MARKER_FILE_NAME))
+            .ok()
+            .map(|id| id.trim().to_string());
+        let update = applied_id.as_ref().and_then(|id| self.updates.get(id).cloned());
+        let target_update = release_id.as_ref().and_then(|id| self.updates.get(id).cloned());
+
+        let settings = self._settings.clone();
+        let app_dir = app_dir.to_path_buf();
+        let executable_path = executable_path.to_path_buf();
+        let command = command.clone();
+        let store_root = self.store_root_folder.clone();
+        let events = self.events.clone();
+        let replaced_id = applied_id.clone();
+
+        thread::spawn(move || {
+            if settings.app_is_systemd_service {
+                Self::stop_app_service(&settings);
+            } else if let Some(pid) = pid {
+                println!("Stopping PID {}...", pid);
+                if let Err(e) = Command::new("kill").arg("-TERM").arg(pid.to_string()).output() {
+                    eprintln!("WARNING: Failed to signal PID {}: {}", pid, e);
+                }
+
+                let proc_folder = format!("/proc/{}", pid);
+                let start_time = Instant::now();
+                while Path::new(&proc_folder).is_dir() {
+                    if start_time.elapsed().as_secs() >= settings.update_apply_timeout_seconds {
+                        eprintln!("ERROR: PID {} did not exit within {} seconds; version change aborted",
+                            pid, settings.update_apply_timeout_seconds);
+                        return;
+                    }
+                    sleep(Duration::from_millis(1000));
+                }
+            }
+
+            let reason = match release_id {
+                None => {
+                    if let Err(e) = Self::restore_previous_version(&settings, &app_dir) {
+                        eprintln!("ERROR: Rollback failed: {}", e);
+                        return;
+                    }
+                    metrics().rollbacks.with_label_values(&["requested"]).inc();
+                    "requested over REST".to_string()
+                },
+                Some(ref id) => {
+                    if let Err(e) = ReleaseStore::new(&settings).activate(id) {
+                        eprintln!("ERROR: Failed to switch release: {}", e);
+                        return;
+                    }
+                    metrics().rollbacks.with_label_values(&["release_switch"]).inc();
+                    format!("switched to release '{}'", id)
+                }
+            };
+
+            match update {
+                Some(update) if replaced_id != release_id => Self::mark_update_rolled_back(&update, &store_root, &events, &reason, false),
+                Some(_) => {},
+                None => println!("WARNING: Version changed, but no applied update is on record"),
+            }
+            if let Some(ref target_update) = target_update {
+                Self::mark_update_applied(target_update, &store_root, &events);
+            }
+
+            let _ = Self::start_app(&settings, &executable_path, &app_dir, &command);
+        });
+
+        Ok(applied_id)
+    }
+
+    pub async fn apply_update(&self, id: &String, app_path: &PathBuf, pid: i32, command: &Option<String>) -> Result<u64, UpdateError> {
         println!("APPLYING UPDATE {}", id);
 
         let p = app_path.clone();
         let update = match self.updates.get(id) {
             Some(u) => u.clone(),
             None => {
-                let msg = format!("Update {} not found in store", id);
-                eprintln!("ERROR: {}", msg);
-                return Err(msg);
+                let err = UpdateError::NotFound(id.clone());
+                eprintln!("ERROR: {}", err);
+                return Err(err);
             }
         };
 
         // extract the update to a temp location
-        let d = match update.lock() {
+        let mut d = match update.lock() {
             Ok(descriptor) => descriptor,
             Err(e) => {
                 let msg = format!("Failed to lock update descriptor: {}", e);
                 eprintln!("ERROR: {}", msg);
-                return Err(msg);
+                return Err(UpdateError::Internal(msg));
             }
         };
         let package_path = format!("{}/{}/update.mpak", self.store_root_folder.display(), d.mpak_id);
+        if !Path::new(&package_path).is_file() {
+            let err = UpdateError::NotDownloaded(id.clone());
+            eprintln!("ERROR: {}", err);
+            return Err(err);
+        }
         let update_temp_path = &self._settings.temp_extract_path;
 
+        // the package was checked when it was downloaded, but it has been sitting on disk since
+        if self._settings.require_signed_updates {
+            let signature_path = self.store_root_folder.join(&d.mpak_id).join(Self::SIGNATURE_FILE_NAME);
+            let verified = fs::read(&signature_path)
+                .map_err(|e| UpdateError::BadSignature(format!("Package for update {} has no signature: {}", id, e)))
+                .and_then(|signature| Self::verify_signature(&self._settings, Path::new(&package_path), &signature));
+            if let Err(err) = verified {
+                eprintln!("ERROR: Refusing to apply update {}: {}", id, err);
+                d.set_error(UpdateStatus::Failed, err.to_string());
+                self.save_or_update(&d);
+                return Err(err);
+            }
+        }
+
         // Clean temp folder before extracting
         if update_temp_path.exists() {
             println!("Cleaning temp extract folder: {:?}", update_temp_path);
@@ -502,23 +1019,36 @@ impl UpdateStore {
         if let Err(e) = fs::create_dir_all(update_temp_path) {
             let msg = format!("Failed to create temp extract directory: {}", e);
             eprintln!("ERROR: {}", msg);
-            return Err(msg);
+            d.set_error(UpdateStatus::Failed, msg.clone());
+            self.save_or_update(&d);
+            return Err(UpdateError::Io(msg));
         }
 
         let update_temp_path_str = update_temp_path.to_string_lossy().to_string();
-        if let Err(e) = self.extract_package_to_location(package_path, &update_temp_path_str) {
-            let msg = format!("Failed to extract package: {}", e);
+        if let Err(err) = self.extract_package_to_location(package_path, &update_temp_path_str) {
+            let msg = format!("Failed to extract package: {}", err);
             eprintln!("ERROR: {}", msg);
-            return Err(msg);
+            // don't leave a half-extracted package behind
+            let _ = fs::remove_dir_all(update_temp_path);
+            d.set_error(UpdateStatus::Failed, msg);
+            self.save_or_update(&d);
+            return Err(err);
         }
 
         // make sure it's a valid app update (i.e. has an `app` folder)
         let update_source_folder = update_temp_path.join("app");
         if !update_source_folder.is_dir() {
             println!("Not a valid app update");
-            return Err("Package does not contain a valid Application update".to_string());
+            let msg = "Package does not contain a valid Application update".to_string();
+            d.set_error(UpdateStatus::Failed, msg.clone());
+            self.save_or_update(&d);
+            return Err(UpdateError::BadPackage(msg));
         }
 
+        // extracted; the rest happens once the app gets out of the way
+        d.set_status(UpdateStatus::Staged);
+        self.save_or_update(&d);
+
         // spawn a thread to wait for app shutdown
         let local_command = command.clone();
         let timeout_seconds = self._settings.update_apply_timeout_seconds;
@@ -526,12 +1056,21 @@ impl UpdateStore {
         let update_id = id.clone();
         let store_root = self.store_root_folder.clone();
         let settings = self._settings.clone();
+        let update_confirmed = self.update_confirmed.clone();
+        let events = self.events.clone();
+        drop(d);
 
         thread::spawn(move || {
+            let timer = ApplyTimer::start();
+            let fail = |reason: String| {
+                Self::set_update_status(&update, &store_root, &events, UpdateStatus::Failed, Some(reason));
+            };
+
             let application_folder = match p.parent().and_then(|p| p.to_str()) {
                 Some(folder) => folder,
                 None => {
                     eprintln!("ERROR: Failed to get application folder from path");
+                    fail("Failed to get application folder from path".to_string());
                     return;
                 }
             };
@@ -539,6 +1078,7 @@ impl UpdateStore {
                 Some(name) => name,
                 None => {
                     eprintln!("ERROR: Failed to get application name from path");
+                    fail("Failed to get application name from path".to_string());
                     return;
                 }
             };
@@ -591,7 +1131,7 @@ impl UpdateStore {
                     println!("ERROR: Timeout waiting for '{}' to exit after {} seconds", app, timeout_seconds);
                     println!("Cleaning up temp extraction folder: {}", temp_path.display());
                     let _ = fs::remove_dir_all(&temp_path);
-                    // TODO: Mark update as "failed" in descriptor
+                    fail(format!("Timeout waiting for '{}' to exit after {} seconds", app, timeout_seconds));
                     return;
                 }
 
@@ -608,12 +1148,14 @@ impl UpdateStore {
                     },
                     _ => {
                         println!("'{}' exited after {} seconds", &app, start_time.elapsed().as_secs());
+                        Self::set_update_status(&update, &store_root, &events, UpdateStatus::Applying, None);
 
                         // Get application directory
                         let app_dir = match Self::get_app_directory(&p) {
                             Ok(dir) => dir,
                             Err(e) => {
                                 eprintln!("ERROR: Failed to get application directory: {}", e);
+                                fail(format!("Failed to get application directory: {}", e));
                                 eprintln!("Cleaning up temp extraction folder: {}", temp_path.display());
                                 let _ = fs::remove_dir_all(&temp_path);
                                 return;
@@ -632,6 +1174,7 @@ impl UpdateStore {
                             println!("Removing existing temp staging directory: {:?}", temp_staging_dir);
                             if let Err(e) = fs::remove_dir_all(&temp_staging_dir) {
                                 eprintln!("ERROR: Failed to remove existing temp staging directory: {}", e);
+                                fail(format!("Failed to remove existing temp staging directory: {}", e));
                                 eprintln!("Cleaning up temp extraction folder: {}", temp_path.display());
                                 let _ = fs::remove_dir_all(&temp_path);
                                 return;
@@ -641,6 +1184,7 @@ impl UpdateStore {
                         // Create temp staging directory
                         if let Err(e) = fs::create_dir_all(&temp_staging_dir) {
                             eprintln!("ERROR: Failed to create temp staging directory: {}", e);
+                            fail(format!("Failed to create temp staging directory: {}", e));
                             eprintln!("Cleaning up temp extraction folder: {}", temp_path.display());
                             let _ = fs::remove_dir_all(&temp_path);
                             return;
@@ -648,12 +1192,11 @@ impl UpdateStore {
 
                         // Copy new files from extracted package to temp staging directory
                         println!("Copying new files from package to {:?}", temp_staging_dir);
-                        let opts = fs_extra::dir::CopyOptions::new()
-                            .overwrite(true)
-                            .content_only(true);
-
-                        if let Err(e) = fs_extra::dir::copy(&update_source_folder, &temp_staging_dir, &opts) {
+                        // New files belong to whoever owns the app directory, not the daemon
+                        let app_owner = fs::metadata(&app_dir).ok().map(|m| (m.uid(), m.gid()));
+                        if let Err(e) = Self::copy_tree(&update_source_folder, &temp_staging_dir, app_owner) {
                             eprintln!("ERROR: Failed to copy new files: {}", e);
+                            fail(format!("Failed to copy new files: {}", e));
                             eprintln!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                             let _ = fs::remove_dir_all(&temp_staging_dir);
                             eprintln!("Cleaning up temp extraction folder: {}", temp_path.display());
@@ -666,6 +1209,7 @@ impl UpdateStore {
                             Ok(files) => files,
                             Err(e) => {
                                 eprintln!("ERROR: Failed to collect package files: {}", e);
+                                fail(format!("Failed to collect package files: {}", e));
                                 eprintln!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                                 let _ = fs::remove_dir_all(&temp_staging_dir);
                                 eprintln!("Cleaning up temp extraction folder: {}", temp_path.display());
@@ -681,9 +1225,17 @@ impl UpdateStore {
                         match Self::merge_preserved_files(&app_dir, &temp_staging_dir, &new_files) {
                             Ok(count) => {
                                 println!("Preserved {} files from current version", count);
+
+                                // The staging directory replaces the app directory, so it takes over its mode and owner
+                                if let Err(e) = fs::metadata(&app_dir)
+                                    .map_err(|e| format!("Failed to read metadata for {:?}: {}", app_dir, e))
+                                    .and_then(|metadata| Self::copy_metadata(&metadata, &temp_staging_dir, None)) {
+                                    eprintln!("WARNING: {}", e);
+                                }
                             }
                             Err(e) => {
                                 eprintln!("ERROR: Failed to merge preserved files: {}", e);
+                                fail(format!("Failed to merge preserved files: {}", e));
                                 eprintln!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                                 let _ = fs::remove_dir_all(&temp_staging_dir);
                                 eprintln!("Cleaning up temp extraction folder: {}", temp_path.display());
@@ -698,8 +1250,9 @@ impl UpdateStore {
                         println!("Rollback directory: {:?}", rollback_dir);
 
                         // Perform directory swap (will use file-by-file for cross-filesystem)
-                        if let Err(e) = Self::swap_with_fallback(&app_dir, &temp_staging_dir, &rollback_dir) {
+                        if let Err(e) = Self::install_staged_app(&settings, &app_dir, &temp_staging_dir, &update_id) {
                             eprintln!("ERROR: Directory swap failed: {}", e);
+                            fail(format!("Directory swap failed: {}", e));
                             eprintln!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                             let _ = fs::remove_dir_all(&temp_staging_dir);
                             eprintln!("Cleaning up temp extraction folder: {}", temp_path.display());
@@ -707,9 +1260,6 @@ impl UpdateStore {
                             return;
                         }
 
-                        // Mark update as "applied" in descriptor
-                        Self::mark_update_applied(&update_id, &store_root);
-
                         // Clean up temp staging directory
                         println!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                         let _ = fs::remove_dir_all(&temp_staging_dir);
@@ -718,61 +1268,28 @@ impl UpdateStore {
                         println!("Cleaning up temp extraction folder: {}", temp_path.display());
                         let _ = fs::remove_dir_all(&temp_path);
 
-                        // Update completed successfully
-                        println!("Update applied successfully!");
-                        println!("  Active version: {:?}", app_dir);
-                        println!("  Rollback available: {:?}", rollback_dir);
-
-                        // Restart the app
-                        if settings.app_is_systemd_service {
-                            // Restart via systemd
-                            if let Some(ref service_name) = settings.app_service_name {
-                                println!("Starting systemd service '{}'...", service_name);
-                                match Command::new("systemctl")
-                                    .arg("start")
-                                    .arg(service_name)
-                                    .output() {
-                                    Ok(output) => {
-                                        if output.status.success() {
-                                            println!("Successfully started service '{}'", service_name);
-                                        } else {
-                                            eprintln!("ERROR: Failed to start service '{}': {}",
-                                                service_name,
-                                                String::from_utf8_lossy(&output.stderr));
-                                        }
-                                    },
+                        // Restart the app and make sure the new version stays up before calling it applied
+                        update_confirmed.store(false, Ordering::SeqCst);
+                        let mut app_process = Self::start_app(&settings, &p, Path::new(application_folder), &local_command);
+                        match Self::check_app_health(&settings, &mut app_process, &update_confirmed) {
+                            Ok(()) => {
+                                Self::mark_update_applied(&update, &store_root, &events);
+                                timer.succeeded();
+
+                                println!("Update applied successfully!");
+                                println!("  Active version: {:?}", app_dir);
+                                println!("  Rollback available: {:?}", rollback_dir);
+                            },
+                            Err(reason) => {
+                                eprintln!("ERROR: Update {} failed its health check: {}", update_id, reason);
+                                match Self::roll_back_app(&settings, app_process, &app_dir, &p, &local_command) {
+                                    Ok(()) => Self::mark_update_rolled_back(&update, &store_root, &events, &reason, true),
                                     Err(e) => {
-                                        eprintln!("ERROR: Failed to execute systemctl start: {}", e);
+                                        // the failed version is still the one installed
+                                        eprintln!("ERROR: Rollback failed: {}", e);
+                                        fail(format!("{}; rollback failed: {}", reason, e));
                                     }
                                 }
-                            } else {
-                                eprintln!("ERROR: app_is_systemd_service is true but app_service_name is not set!");
-                            }
-                        } else {
-                            // Direct process spawn
-                            println!("Launching '{:?}' in directory '{}'...", p, application_folder);
-                            match local_command {
-                                None => {
-                                    let _app = Command::new(&p)
-                                        .current_dir(application_folder)
-                                        .stdin(Stdio::null())
-                                        .stdout(Stdio::null())
-                                        .stderr(Stdio::null())
-                                        .process_group(0)
-                                        .spawn()
-                                        .expect("Failed to start process");
-                                },
-                                Some(cmd) => {
-                                    let _app = Command::new(cmd)
-                                        .arg(&p)
-                                        .current_dir(application_folder)
-                                        .stdin(Stdio::null())
-                                        .stdout(Stdio::null())
-                                        .stderr(Stdio::null())
-                                        .process_group(0)
-                                        .spawn()
-                                        .expect("Failed to start process");
-                                },
                             }
                         }
 
@@ -795,9 +1312,16 @@ impl UpdateStore {
     /// * `executable_path` - Full path to executable/DLL for restart (can be relative to app_dir)
     /// * `pid` - Process ID to wait for before applying
     /// * `command` - Optional command to use for restart (e.g., "dotnet" for .NET apps)
-    pub async fn apply_extracted_update(&self, app_dir: &PathBuf, executable_path: &PathBuf, pid: i32, command: &Option<String>) -> Result<u64, String> {
+    pub async fn apply_extracted_update(&self, app_dir: &PathBuf, executable_path: &PathBuf, pid: i32, command: &Option<String>) -> Result<u64, UpdateError> {
         println!("APPLYING EXTRACTED UPDATE (no tracking)");
 
+        // there's no package left to check, so this would be a way around signing altogether
+        if self._settings.require_signed_updates {
+            let msg = "require_signed_updates is set; externally extracted updates can't be verified and are refused".to_string();
+            eprintln!("ERROR: {}", msg);
+            return Err(UpdateError::BadSignature(msg));
+        }
+
         // Verify the extracted update exists
         let update_temp_path = &self._settings.temp_extract_path;
         let update_source_folder = update_temp_path.join("app");
@@ -805,13 +1329,13 @@ impl UpdateStore {
         if !update_source_folder.exists() {
             let msg = format!("Extracted update not found at {:?}", update_source_folder);
             eprintln!("ERROR: {}", msg);
-            return Err(msg);
+            return Err(UpdateError::NotExtracted(msg));
         }
 
         if !update_source_folder.is_dir() {
             let msg = format!("Update source is not a directory: {:?}", update_source_folder);
             eprintln!("ERROR: {}", msg);
-            return Err(msg);
+            return Err(UpdateError::NotExtracted(msg));
         }
 
         println!("Update source folder: {:?}", update_source_folder);
@@ -823,8 +1347,10 @@ impl UpdateStore {
         let timeout_seconds = self._settings.update_apply_timeout_seconds;
         let temp_path = update_temp_path.clone();
         let settings = self._settings.clone();
+        let update_confirmed = self.update_confirmed.clone();
 
         thread::spawn(move || {
+            let timer = ApplyTimer::start();
             let app_dir_str = app_dir_clone.to_string_lossy().to_string();
             let executable_name = executable_path_clone.file_name()
                 .and_then(|n| n.to_str())
@@ -922,11 +1448,9 @@ impl UpdateStore {
 
                         // Copy new files from extracted package to temp staging directory
                         println!("Copying new files from package to {:?}", temp_staging_dir);
-                        let opts = fs_extra::dir::CopyOptions::new()
-                            .overwrite(true)
-                            .content_only(true);
-
-                        if let Err(e) = fs_extra::dir::copy(&update_source_folder, &temp_staging_dir, &opts) {
+                        // New files belong to whoever owns the app directory, not the daemon
+                        let app_owner = fs::metadata(&app_dir_clone).ok().map(|m| (m.uid(), m.gid()));
+                        if let Err(e) = Self::copy_tree(&update_source_folder, &temp_staging_dir, app_owner) {
                             eprintln!("ERROR: Failed to copy new files: {}", e);
                             eprintln!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                             let _ = fs::remove_dir_all(&temp_staging_dir);
@@ -955,6 +1479,13 @@ impl UpdateStore {
                         match Self::merge_preserved_files(&app_dir_clone, &temp_staging_dir, &new_files) {
                             Ok(count) => {
                                 println!("Preserved {} files from current version", count);
+
+                                // The staging directory replaces the app directory, so it takes over its mode and owner
+                                if let Err(e) = fs::metadata(&app_dir_clone)
+                                    .map_err(|e| format!("Failed to read metadata for {:?}: {}", app_dir_clone, e))
+                                    .and_then(|metadata| Self::copy_metadata(&metadata, &temp_staging_dir, None)) {
+                                    eprintln!("WARNING: {}", e);
+                                }
                             }
                             Err(e) => {
                                 eprintln!("ERROR: Failed to merge preserved files: {}", e);
@@ -971,8 +1502,14 @@ impl UpdateStore {
                         println!("Staging directory: {:?}", temp_staging_dir);
                         println!("Rollback directory: {:?}", rollback_dir);
 
+                        // Untracked updates have no mpak id to name their release after
+                        let release_id = format!("app-{}", std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0));
+
                         // Perform directory swap (will use file-by-file for cross-filesystem)
-                        if let Err(e) = Self::swap_with_fallback(&app_dir_clone, &temp_staging_dir, &rollback_dir) {
+                        if let Err(e) = Self::install_staged_app(&settings, &app_dir_clone, &temp_staging_dir, &release_id) {
                             eprintln!("ERROR: Directory swap failed: {}", e);
                             eprintln!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                             let _ = fs::remove_dir_all(&temp_staging_dir);
@@ -985,67 +1522,29 @@ impl UpdateStore {
                         println!("Cleaning up temp staging directory: {:?}", temp_staging_dir);
                         let _ = fs::remove_dir_all(&temp_staging_dir);
 
-                        // Note: No update tracking for this method (no mark_update_applied call)
-
                         // Clean up temp extraction folder
                         println!("Cleaning up temp extraction folder: {}", temp_path.display());
                         let _ = fs::remove_dir_all(&temp_path);
 
-                        // Update completed successfully
-                        println!("Update applied successfully!");
-                        println!("  Active version: {:?}", app_dir_clone);
-                        println!("  Rollback available: {:?}", rollback_dir);
-
-                        // Restart the app
-                        if settings.app_is_systemd_service {
-                            // Restart via systemd
-                            if let Some(ref service_name) = settings.app_service_name {
-                                println!("Starting systemd service '{}'...", service_name);
-                                match Command::new("systemctl")
-                                    .arg("start")
-                                    .arg(service_name)
-                                    .output() {
-                                    Ok(output) => {
-                                        if output.status.success() {
-                                            println!("Successfully started service '{}'", service_name);
-                                        } else {
-                                            eprintln!("ERROR: Failed to start service '{}': {}",
-                                                service_name,
-                                                String::from_utf8_lossy(&output.stderr));
-                                        }
-                                    },
-                                    Err(e) => {
-                                        eprintln!("ERROR: Failed to execute systemctl start: {}", e);
-                                    }
+                        // Restart the app and make sure the new version stays up before calling it applied
+                        // Note: No update tracking for this method (no mark_update_applied call), only metrics
+                        update_confirmed.store(false, Ordering::SeqCst);
+                        let mut app_process = Self::start_app(&settings, &executable_path_clone, &app_dir_clone, &local_command);
+                        match Self::check_app_health(&settings, &mut app_process, &update_confirmed) {
+                            Ok(()) => {
+                                timer.succeeded();
+
+                                println!("Update applied successfully!");
+                                println!("  Active version: {:?}", app_dir_clone);
+                                println!("  Rollback available: {:?}", rollback_dir);
+                            },
+                            Err(reason) => {
+                                // dropping the timer counts the apply as failed, whether or not the rollback works
+                                eprintln!("ERROR: Update failed its health check: {}", reason);
+                                match Self::roll_back_app(&settings, app_process, &app_dir_clone, &executable_path_clone, &local_command) {
+                                    Ok(()) => println!("Update rolled back: {}", reason),
+                                    Err(e) => eprintln!("ERROR: Rollback failed; the failed version is still installed: {}", e),
                                 }
-                            } else {
-                                eprintln!("ERROR: app_is_systemd_service is true but app_service_name is not set!");
-                            }
-                        } else {
-                            // Direct process spawn
-                            println!("Launching '{:?}' in directory '{:?}'...", executable_path_clone, app_dir_clone);
-                            match local_command {
-                                None => {
-                                    let _app = Command::new(&executable_path_clone)
-                                        .current_dir(&app_dir_clone)
-                                        .stdin(Stdio::null())
-                                        .stdout(Stdio::null())
-                                        .stderr(Stdio::null())
-                                        .process_group(0)
-                                        .spawn()
-                                        .expect("Failed to start process");
-                                },
-                                Some(cmd) => {
-                                    let _app = Command::new(cmd)
-                                        .arg(&executable_path_clone)
-                                        .current_dir(&app_dir_clone)
-                                        .stdin(Stdio::null())
-                                        .stdout(Stdio::null())
-                                        .stderr(Stdio::null())
-                                        .process_group(0)
-                                        .spawn()
-                                        .expect("Failed to start process");
-                                },
                             }
                         }
 
@@ -1058,37 +1557,10 @@ impl UpdateStore {
         Ok(1)
     }
 
-    fn _extract_update_to_location(_update: Arc<Mutex<UpdateDescriptor>>, file_name: String, destination_root: &String) -> Result<u64, String> {
+    fn _extract_update_to_location(_update: Arc<Mutex<UpdateDescriptor>>, file_name: String, destination_root: &String) -> Result<u64, UpdateError> {
 //            let mut d = update.lock().unwrap();
 
-            let zip_file = File::open(&file_name)
-                .map_err(|e| format!("Failed to open zip file '{}': {}", file_name, e))?;
-            let mut archive = ZipArchive::new(zip_file)
-                .map_err(|e| format!("Failed to read zip archive: {}", e))?;
-
-            for i in 0..archive.len() {
-                let mut file = archive.by_index(i)
-                    .map_err(|e| format!("Failed to read zip entry {}: {}", i, e))?;
-                let outpath = Path::new(&destination_root).join(file.name());
-                if (&*file.name()).ends_with('/') {
-                    std::fs::create_dir_all(&outpath)
-                        .map_err(|e| format!("Failed to create directory '{}': {}", outpath.display(), e))?;
-                }
-                else {
-                    if let Some(p) = outpath.parent() {
-                        if !p.exists() {
-                            std::fs::create_dir_all(&p)
-                                .map_err(|e| format!("Failed to create parent directory '{}': {}", p.display(), e))?;
-                        }
-                    }
-                    let mut outfile = File::create(&outpath)
-                        .map_err(|e| format!("Failed to create output file '{}': {}", outpath.display(), e))?;
-                    std::io::copy(&mut file, &mut outfile)
-                        .map_err(|e| format!("Failed to write to file '{}': {}", outpath.display(), e))?;
-                }
-            };
-
-            Ok(1)
+            Self::extract_archive(Path::new(&file_name), Path::new(destination_root))
 
 /*            
                 // mark as "applied"
@@ -1099,76 +1571,204 @@ impl UpdateStore {
 */
     }
 
-    fn extract_package_to_location(&self, package_path: String, destination_root: &String) -> Result<u64, String> {
-        let zip_file = File::open(&package_path)
-            .map_err(|e| format!("Failed to open package '{}': {}", package_path, e))?;
+    fn extract_package_to_location(&self, package_path: String, destination_root: &String) -> Result<u64, UpdateError> {
+        Self::extract_archive(Path::new(&package_path), Path::new(destination_root))
+    }
+
+    /// Extract an update package into `destination_root`
+    ///
+    /// Every entry is checked before anything is written, so a bad package is refused as a
+    /// whole rather than partially extracted: names must stay inside the destination (no
+    /// absolute paths or escaping `..`), symlinks must point inside the package, nothing may
+    /// be extracted through a symlink, and device files, FIFOs and sockets are rejected.
+    ///
+    /// Symlinks are recreated as symlinks and the stored Unix permission bits are applied;
+    /// setuid, setgid and sticky bits from a package are never honored.
+    pub fn extract_archive(package_path: &Path, destination_root: &Path) -> Result<u64, UpdateError> {
+        let zip_file = File::open(package_path)
+            .map_err(|e| UpdateError::Io(format!("Failed to open package '{}': {}", package_path.display(), e)))?;
         let mut archive = ZipArchive::new(zip_file)
-            .map_err(|e| format!("Failed to read package archive: {}", e))?;
+            .map_err(|e| UpdateError::BadPackage(format!("Failed to read package archive: {}", e)))?;
 
-        for i in 0..archive.len() {
+        let entry_paths = Self::validate_archive(&mut archive)
+            .map_err(UpdateError::BadPackage)?;
+        Self::write_archive(&mut archive, &entry_paths, destination_root)
+            .map_err(UpdateError::Io)?;
+
+        Ok(entry_paths.len() as u64)
+    }
+
+    /// Write out the entries of an archive that `validate_archive` has accepted
+    fn write_archive<R: Read + Seek>(archive: &mut ZipArchive<R>, entry_paths: &[PathBuf], destination_root: &Path) -> Result<(), String> {
+        let mut directory_modes = Vec::new();
+
+        for (i, entry_path) in entry_paths.iter().enumerate() {
             let mut file = archive.by_index(i)
                 .map_err(|e| format!("Failed to read archive entry {}: {}", i, e))?;
-            let outpath = Path::new(&destination_root).join(file.name());
-            if (&*file.name()).ends_with('/') {
+            let outpath = destination_root.join(entry_path);
+            let mode = file.unix_mode();
+            if file.is_dir() {
                 std::fs::create_dir_all(&outpath)
                     .map_err(|e| format!("Failed to create directory '{}': {}", outpath.display(), e))?;
+                if let Some(mode) = mode {
+                    directory_modes.push((outpath, mode));
+                }
             }
             else {
                 if let Some(p) = outpath.parent() {
                     if !p.exists() {
-                        std::fs::create_dir_all(&p)
+                        std::fs::create_dir_all(p)
                             .map_err(|e| format!("Failed to create parent directory '{}': {}", p.display(), e))?;
                     }
                 }
+
+                if mode.map(|m| m & Self::S_IFMT) == Some(Self::S_IFLNK) {
+                    let mut target = String::new();
+                    file.read_to_string(&mut target)
+                        .map_err(|e| format!("Failed to read symlink target of '{}': {}", outpath.display(), e))?;
+                    symlink(&target, &outpath)
+                        .map_err(|e| format!("Failed to create symlink '{}': {}", outpath.display(), e))?;
+                    continue;
+                }
+
                 let mut outfile = File::create(&outpath)
                     .map_err(|e| format!("Failed to create file '{}': {}", outpath.display(), e))?;
                 std::io::copy(&mut file, &mut outfile)
                     .map_err(|e| format!("Failed to copy file data to '{}': {}", outpath.display(), e))?;
+
+                if let Some(mode) = mode {
+                    fs::set_permissions(&outpath, fs::Permissions::from_mode(mode & Self::PACKAGE_MODE_MASK))
+                        .map_err(|e| format!("Failed to set permissions of '{}': {}", outpath.display(), e))?;
+                }
             }
         }
 
-        Ok(1)
+        // directories last, so a read-only directory doesn't stop us filling it
+        for (path, mode) in directory_modes {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & Self::PACKAGE_MODE_MASK))
+                .map_err(|e| format!("Failed to set permissions of '{}': {}", path.display(), e))?;
+        }
+
+        Ok(())
     }
 
-    async fn _extract_app_update(&self, id: &String, destination_root: String) -> Result<u64, String> {
+    /// Check every entry of a package and return the relative path each one extracts to
+    fn validate_archive<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Vec<PathBuf>, String> {
+        let mut entry_paths = Vec::with_capacity(archive.len());
+        let mut symlinks: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)
+                .map_err(|e| format!("Failed to read archive entry {}: {}", i, e))?;
+            let name = file.name().to_string();
+
+            let entry_path = file.enclosed_name()
+                .and_then(|p| Self::normalize_relative_path(&p))
+                .ok_or_else(|| format!("Package entry '{}' would be extracted outside the destination", name))?;
+
+            match file.unix_mode().map(|mode| mode & Self::S_IFMT) {
+                None | Some(0) | Some(Self::S_IFREG) | Some(Self::S_IFDIR) => {},
+                Some(Self::S_IFLNK) => {
+                    let mut target = String::new();
+                    file.read_to_string(&mut target)
+                        .map_err(|e| format!("Failed to read symlink target of '{}': {}", name, e))?;
+
+                    if Path::new(&target).is_absolute() {
+                        return Err(format!("Package entry '{}' is a symlink pointing outside the package ({})", name, target));
+                    }
+
+                    symlinks.insert(entry_path.clone(), PathBuf::from(target));
+                },
+                Some(_) => {
+                    return Err(format!("Package entry '{}' is a device file, FIFO or socket", name));
+                }
+            }
+
+            entry_paths.push(entry_path);
+        }
+
+        // targets are resolved against every link in the package, since one link can lead through another
+        for (link, target) in &symlinks {
+            let parent = link.parent().unwrap_or(Path::new("")).to_path_buf();
+            if Self::resolve_package_link(&symlinks, parent, target, 0).is_none() {
+                return Err(format!("Package entry '{}' is a symlink pointing outside the package ({})", link.display(), target.display()));
+            }
+        }
+
+        // a symlink inside the package must not become a way out of it
+        for entry_path in &entry_paths {
+            if entry_path.ancestors().skip(1).any(|a| symlinks.contains_key(a)) {
+                return Err(format!("Package entry '{}' would be extracted through a symlink", entry_path.display()));
+            }
+        }
+
+        Ok(entry_paths)
+    }
+
+    /// Walk `target` from `base` one component at a time, following any package symlink on the way
+    ///
+    /// Returns None if the walk climbs above the package root, is absolute or loops.
+    fn resolve_package_link(symlinks: &HashMap<PathBuf, PathBuf>, base: PathBuf, target: &Path, depth: usize) -> Option<PathBuf> {
+        if depth > Self::MAX_SYMLINK_DEPTH {
+            return None;
+        }
+
+        let mut resolved = base;
+        for component in target.components() {
+            match component {
+                Component::Normal(part) => {
+                    resolved.push(part);
+                    if let Some(next) = symlinks.get(&resolved) {
+                        resolved.pop();
+                        resolved = Self::resolve_package_link(symlinks, resolved, next, depth + 1)?;
+                    }
+                },
+                Component::CurDir => {},
+                Component::ParentDir => {
+                    if !resolved.pop() {
+                        return None;
+                    }
+                },
+                Component::RootDir | Component::Prefix(_) => return None,
+            }
+        }
+        Some(resolved)
+    }
+
+    /// Resolve `.` and `..` in a relative path without touching the filesystem
+    ///
+    /// Returns None if the path is absolute or climbs above its starting point.
+    fn normalize_relative_path(path: &Path) -> Option<PathBuf> {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => normalized.push(part),
+                Component::CurDir => {},
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return None;
+                    }
+                },
+                Component::RootDir | Component::Prefix(_) => return None,
+            }
+        }
+        Some(normalized)
+    }
+
+    async fn _extract_app_update(&self, id: &String, destination_root: String) -> Result<u64, UpdateError> {
         let update = self.updates.get(id);
         match update {
             Some(u) => {
                 let mut d = match u.lock() {
                     Ok(descriptor) => descriptor,
                     Err(e) => {
-                        return Err(format!("Failed to lock update descriptor: {}", e));
+                        return Err(UpdateError::Internal(format!("Failed to lock update descriptor: {}", e)));
                     }
                 };
 
                 let file_name = format!("{}/{}/update.mpak", self.store_root_folder.display(), d.mpak_id);
 
-                let zip_file = File::open(&file_name)
-                    .map_err(|e| format!("Failed to open update package '{}': {}", file_name, e))?;
-                let mut archive = ZipArchive::new(zip_file)
-                    .map_err(|e| format!("Failed to read archive: {}", e))?;
-
-                for i in 0..archive.len() {
-                    let mut file = archive.by_index(i)
-                        .map_err(|e| format!("Failed to read archive entry {}: {}", i, e))?;
-                    let outpath = Path::new(&destination_root).join(file.name());
-                    if (&*file.name()).ends_with('/') {
-                        std::fs::create_dir_all(&outpath)
-                            .map_err(|e| format!("Failed to create directory '{}': {}", outpath.display(), e))?;
-                    }
-                    else {
-                        if let Some(p) = outpath.parent() {
-                            if !p.exists() {
-                                std::fs::create_dir_all(&p)
-                                    .map_err(|e| format!("Failed to create parent directory '{}': {}", p.display(), e))?;
-                            }
-                        }
-                        let mut outfile = File::create(&outpath)
-                            .map_err(|e| format!("Failed to create output file '{}': {}", outpath.display(), e))?;
-                        std::io::copy(&mut file, &mut outfile)
-                            .map_err(|e| format!("Failed to copy file to '{}': {}", outpath.display(), e))?;
-                    }
-                }
+                Self::extract_archive(Path::new(&file_name), Path::new(&destination_root))?;
             
                 // mark as "applied"
                 d.applied = Some(true);
@@ -1181,115 +1781,206 @@ impl UpdateStore {
             },
             None => {
 
-                Err(format!("Update {} not known", id))
+                Err(UpdateError::NotFound(id.clone()))
             }
         }
     }
 
+    /// Use a new token for downloads, including ones already under way
     pub fn set_jwt(&mut self, jwt: String) {
-        self.jwt = jwt;
+        match self.jwt.lock() {
+            Ok(mut current) => *current = jwt,
+            Err(e) => eprintln!("ERROR: Failed to lock token: {}", e)
+        }
     }
 
-    pub async fn retrieve_update(&self, id: &String) -> Result<u64, String> {
-        
+    /// Whether a download was refused for want of a valid token since the last call
+    pub fn take_reauth_request(&self) -> bool {
+        self.reauth_requested.swap(false, Ordering::SeqCst)
+    }
+
+    /// Download an update and wait for it to finish
+    ///
+    /// This holds `&self` for the whole transfer; callers that share the store should use
+    /// `begin_download` and run the job after releasing their lock.
+    pub async fn retrieve_update(&self, id: &String) -> Result<u64, UpdateError> {
+        self.begin_download(id)?.run().await
+    }
+
+    /// Register a download for an update and hand back a job that performs it
+    ///
+    /// The job does not borrow the store, so it can be spawned in the background while
+    /// `get_download_progress` reports how far along it is.
+    pub fn begin_download(&self, id: &String) -> Result<DownloadJob, UpdateError> {
         // is this an update we know about?
-        let update = self.updates.get(id);
-        match update {
-            Some(u) => {
-               let mut d = match u.lock() {
-                   Ok(descriptor) => descriptor,
-                   Err(e) => {
-                       return Err(format!("Failed to lock update descriptor: {}", e));
-                   }
-               };
-
-                let mut sanitized_url = (&d.mpak_download_url).to_string();
-                if !sanitized_url.starts_with("http") {
-                    // TODO: support auth/https
-                    sanitized_url.insert_str(0, "http://");
+        let descriptor = match self.updates.get(id) {
+            Some(u) => u.clone(),
+            None => {
+                return Err(UpdateError::NotFound(id.clone()));
+            }
+        };
 
+        let total_bytes = match descriptor.lock() {
+            Ok(d) if d.file_size != 0 => Some(u64::from(d.file_size)),
+            Ok(_) => None,
+            Err(e) => {
+                return Err(UpdateError::Internal(format!("Failed to lock update descriptor: {}", e)));
+            }
+        };
+
+        match self.downloads.lock() {
+            Ok(mut downloads) => {
+                if downloads.contains_key(id) {
+                    return Err(UpdateError::DownloadInProgress(id.clone()));
                 }
+                downloads.insert(id.clone(), DownloadProgress { bytes_received: 0, total_bytes });
+            },
+            Err(e) => {
+                return Err(UpdateError::Internal(format!("Failed to lock download table: {}", e)));
+            }
+        }
 
-                let client = reqwest::Client::new();
+        Ok(DownloadJob {
+            update_id: id.clone(),
+            descriptor,
+            store_root: self.store_root_folder.clone(),
+            settings: self._settings.clone(),
+            jwt: self.jwt.clone(),
+            reauth_requested: self.reauth_requested.clone(),
+            downloads: self.downloads.clone(),
+            events: self.events.clone(),
+            last_progress_event: AtomicU64::new(0),
+        })
+    }
 
-                let auth_header = match reqwest::header::HeaderValue::from_str(&format!("Bearer {}", self.jwt)) {
-                    Ok(header) => header,
-                    Err(e) => {
-                        return Err(format!("Failed to create auth header: {}", e));
-                    }
-                };
+    /// The running app reports that it came up fine after an update
+    pub fn confirm_update(&self) {
+        self.update_confirmed.store(true, Ordering::SeqCst);
+    }
 
-                match client
-                    .get(sanitized_url)
-                    .header(reqwest::header::AUTHORIZATION, auth_header)
-                    .send()
-                    .await 
-                {            
-                    Ok(response) => {
-                        
-                        // Check for a successful status code
-                        if !response.status().is_success() {
-                            println!("Failed to download file: HTTP {}", response.status());
-                            return Err(format!("Failed to download file: HTTP {}", response.status()));
-                        }                        
-
-                        // determine where to store the mpak - we will extract on apply
-                        let file_name = format!("{}/{}/update.mpak", self.store_root_folder.display(), d.mpak_id);
-
-                        // download the update
-                        //let s = sanitized_url.clone();
-                        //println!("downloading {} to {}", s, file_name);
-                        println!("downloading {}", file_name);
-
-                        let mut file = match File::create(&file_name) {
-                            Ok(f) => f,
-                            Err(e) => {
-                                return Err(format!("Failed to create file '{}': {}", file_name, e));
-                            }
-                        };
+    /// How far along the download of an update is, if one is currently running
+    pub fn get_download_progress(&self, id: &str) -> Option<DownloadProgress> {
+        match self.downloads.lock() {
+            Ok(downloads) => downloads.get(id).cloned(),
+            Err(_) => None
+        }
+    }
 
-                        match response.bytes().await {
-                            Ok(data) => {
-                                let mut content = Cursor::new(data);
-                                let size = match copy(&mut content, &mut file) {
-                                    Ok(s) => s,
-                                    Err(e) => {
-                                        return Err(format!("Failed to write downloaded data to file: {}", e));
-                                    }
-                                };
-                
-                                // set the update as retrieved
-                                d.retrieved = Some(true);
-                
-                                // update file
-                                self.save_or_update(d.deref());
-                
-                                // return the size?  file name?  something
-                                Ok(size)
-        
-                            },
-                            Err(e) => {
-                                return Err(e.to_string());
-                            }
-                        }                                
-                    },
-                    Err(e) => {
-                        return Err(e.to_string());
-                    }
-                }
-            },
-            None => {
+    /// Pull the first byte position out of a `Content-Range: bytes <start>-<end>/<total>` header
+    fn parse_content_range_start(value: &str) -> Option<u64> {
+        value.trim()
+            .strip_prefix("bytes ")?
+            .split('-')
+            .next()?
+            .trim()
+            .parse::<u64>()
+            .ok()
+    }
+
+    /// IDs of updates with an interrupted download that can be resumed
+    pub fn get_partial_downloads(&self) -> Vec<String> {
+        self.updates.values()
+            .filter_map(|u| u.lock().ok())
+            .filter(|d| d.retrieved != Some(true)
+                && (d.status == UpdateStatus::Downloading || d.partial_offset.unwrap_or(0) > 0))
+            .map(|d| d.mpak_id.clone())
+            .collect()
+    }
+
+    /// Check a downloaded package against the size and CRC32 published in its descriptor
+    ///
+    /// The descriptor `crc` is a hex string (an optional `0x` prefix is allowed).  An empty
+    /// `crc` or a zero `file_size` skips that particular check.
+    pub fn verify_package(package_path: &Path, descriptor: &UpdateDescriptor) -> Result<(), UpdateError> {
+        let mut file = File::open(package_path)
+            .map_err(|e| UpdateError::Io(format!("Failed to open package '{}': {}", package_path.display(), e)))?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buffer = [0u8; 64 * 1024];
+        let mut size: u64 = 0;
+        loop {
+            let count = file.read(&mut buffer)
+                .map_err(|e| UpdateError::Io(format!("Failed to read package '{}': {}", package_path.display(), e)))?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+            size += count as u64;
+        }
+
+        if descriptor.file_size != 0 && size != u64::from(descriptor.file_size) {
+            return Err(UpdateError::BadPackage(format!("Package size mismatch: expected {} bytes, got {} bytes", descriptor.file_size, size)));
+        }
 
-                Err(format!("Update {} not known", id))
+        let expected = descriptor.crc.trim();
+        if !expected.is_empty() {
+            let hex = expected.trim_start_matches("0x").trim_start_matches("0X");
+            let expected_crc = u32::from_str_radix(hex, 16)
+                .map_err(|e| UpdateError::BadPackage(format!("Descriptor has an invalid crc '{}': {}", expected, e)))?;
+            let actual_crc = hasher.finalize();
+            if actual_crc != expected_crc {
+                return Err(UpdateError::BadPackage(format!("Package CRC mismatch: expected {:08x}, got {:08x}", expected_crc, actual_crc)));
             }
         }
+
+        Ok(())
+    }
+
+    /// Check a package against its detached signature using the configured trusted keys
+    pub fn verify_signature(settings: &CloudSettings, package_path: &Path, signature: &[u8]) -> Result<(), UpdateError> {
+        let verifier = PackageVerifier::load(&settings.trusted_signing_keys)
+            .map_err(|e| UpdateError::BadSignature(format!("{:#}", e)))?;
+        verifier.verify_file(package_path, signature)
+            .map_err(|e| UpdateError::BadSignature(format!("{:#}", e)))
+    }
+
+    /// The URL to download a package (and its `.sig`) from
+    ///
+    /// A URL without a scheme is taken to be https.  Plain http is only allowed with
+    /// `use_authentication` off, since every package request carries the Meadow.Cloud token.
+    pub fn package_url(url: &str, settings: &CloudSettings) -> Result<String, UpdateError> {
+        let url = url.trim();
+        if url.starts_with("https://") {
+            return Ok(url.to_string());
+        }
+        if url.starts_with("http://") {
+            if settings.use_authentication {
+                return Err(UpdateError::DownloadFailed(format!("Refusing to send the device token to a plain http URL: {}", url)));
+            }
+            println!("WARNING: Downloading {} over plain http", url);
+            return Ok(url.to_string());
+        }
+        if url.contains("://") {
+            return Err(UpdateError::DownloadFailed(format!("Unsupported package URL scheme: {}", url)));
+        }
+        Ok(format!("https://{}", url))
+    }
+
+    /// Verify a freshly fetched signature and only then keep it next to the package for apply
+    ///
+    /// A rejected signature is never written, and one left over from an earlier attempt is removed.
+    pub fn verify_and_store_signature(settings: &CloudSettings, package_path: &Path, signature: &[u8], signature_path: &Path) -> Result<(), UpdateError> {
+        if let Err(err) = Self::verify_signature(settings, package_path, signature) {
+            let _ = fs::remove_file(signature_path);
+            return Err(err);
+        }
+
+        if let Err(e) = fs::write(signature_path, signature) {
+            eprintln!("WARNING: Failed to save package signature '{}': {}", signature_path.display(), e);
+        }
+        Ok(())
     }
 
     fn save_or_update(&self, descriptor: &UpdateDescriptor) {
+        Self::write_descriptor(&self.store_root_folder, descriptor);
+        self.events.publish(DaemonEvent::UpdateChanged { update: descriptor.clone() });
+    }
+
+    fn write_descriptor(store_root: &Path, descriptor: &UpdateDescriptor) {
         println!("{:?}", descriptor);
 
         // make sure subdir exists
-        let mut path = self.store_root_folder.join(&descriptor.mpak_id);
+        let mut path = store_root.join(&descriptor.mpak_id);
         if ! path.exists() {
             if let Err(e) = fs::create_dir(&path) {
                 eprintln!("ERROR: Failed to create update directory '{}': {}", path.display(), e);
@@ -1328,57 +2019,427 @@ impl UpdateStore {
 
     }
 
-    fn mark_update_applied(update_id: &String, store_root: &PathBuf) {
-        let info_path = store_root.join(update_id).join(Self::UPDATE_INFO_FILE_NAME);
+    /// Move an update to a new lifecycle state (with the reason, for failures) and persist it
+    fn set_update_status(update: &Arc<Mutex<UpdateDescriptor>>, store_root: &Path, events: &EventBus, status: UpdateStatus, error: Option<String>) {
+        match update.lock() {
+            Ok(mut d) => {
+                match error {
+                    Some(e) => d.set_error(status, e),
+                    None => d.set_status(status),
+                }
+                Self::write_descriptor(store_root, &d);
+                events.publish(DaemonEvent::UpdateChanged { update: d.clone() });
+            },
+            Err(e) => {
+                eprintln!("ERROR: Failed to lock update descriptor: {}", e);
+            }
+        }
+    }
+
+    fn mark_update_applied(update: &Arc<Mutex<UpdateDescriptor>>, store_root: &Path, events: &EventBus) {
+        Self::set_update_status(update, store_root, events, UpdateStatus::Applied, None);
+
+        let update_id = match update.lock() {
+            Ok(d) => d.mpak_id.clone(),
+            Err(_) => return,
+        };
 
-        if !info_path.exists() {
-            println!("WARNING: Cannot mark update {} as applied - info file not found", update_id);
-            return;
+        // remember which update is live, for a later rollback
+        if let Err(e) = fs::write(store_root.join(Self::APPLIED_MARKER_FILE_NAME), &update_id) {
+            println!("WARNING: Failed to record applied update {}: {:?}", update_id, e);
         }
 
-        // Read the existing descriptor
-        match File::open(&info_path) {
-            Ok(file) => {
-                let reader = BufReader::new(file);
-                match serde_json::from_reader::<_, UpdateDescriptor>(reader) {
-                    Ok(mut descriptor) => {
-                        // Mark as applied
-                        descriptor.applied = Some(true);
+        println!("Marked update {} as applied", update_id);
+    }
 
-                        // Write back to file
-                        let json = match serde_json::to_string_pretty(&descriptor) {
-                            Ok(j) => j,
-                            Err(e) => {
-                                println!("ERROR: Failed to serialize descriptor for {}: {:?}", update_id, e);
-                                return;
-                            }
-                        };
-                        let mut file = match OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .truncate(true)
-                            .open(&info_path) {
-                                Ok(f) => f,
-                                Err(e) => {
-                                    println!("ERROR: Failed to open descriptor file for {}: {:?}", update_id, e);
-                                    return;
-                                }
-                            };
-                        if let Err(e) = file.write_all(json.as_bytes()) {
-                            println!("ERROR: Failed to write descriptor for {}: {:?}", update_id, e);
-                            return;
-                        }
+    /// Record that an applied update was taken back out, either by a failed health check or by request
+    fn mark_update_rolled_back(update: &Arc<Mutex<UpdateDescriptor>>, store_root: &Path, events: &EventBus, reason: &str, failed: bool) {
+        let error = if failed { Some(reason.to_string()) } else { None };
+        Self::set_update_status(update, store_root, events, UpdateStatus::RolledBack, error);
+
+        // the version now running is no longer this update; a failed health check never marked it live
+        let marker = store_root.join(Self::APPLIED_MARKER_FILE_NAME);
+        let rolled_back_id = update.lock().map(|d| d.mpak_id.clone()).ok();
+        if rolled_back_id.is_some() && fs::read_to_string(&marker).ok().map(|id| id.trim().to_string()) == rolled_back_id {
+            let _ = fs::remove_file(&marker);
+        }
+
+        println!("Marked update as rolled back: {}", reason);
+    }
+}
+
+/// A download registered with the `UpdateStore`
+///
+/// Runs without a borrow of the store; progress is published to the store's download
+/// table and descriptor changes are written through to `info.json` as they happen.
+pub struct DownloadJob {
+    update_id: String,
+    descriptor: Arc<Mutex<UpdateDescriptor>>,
+    store_root: PathBuf,
+    settings: CloudSettings,
+    jwt: Arc<Mutex<String>>,
+    reauth_requested: Arc<AtomicBool>,
+    downloads: Arc<Mutex<HashMap<String, DownloadProgress>>>,
+    events: EventBus,
+    last_progress_event: AtomicU64,
+}
+
+impl DownloadJob {
+    pub fn update_id(&self) -> &str {
+        &self.update_id
+    }
+
+    pub async fn run(self) -> Result<u64, UpdateError> {
+        let result = self.download().await;
+        Metrics::record(&metrics().downloads, result.is_ok());
+
+        // whatever went wrong, it should show up on the update
+        if let Err(ref err) = result {
+            match self.descriptor.lock() {
+                Ok(mut d) => {
+                    if d.status != UpdateStatus::Failed {
+                        d.set_error(UpdateStatus::Failed, err.to_string());
+                        UpdateStore::write_descriptor(&self.store_root, &d);
+                        self.events.publish(DaemonEvent::UpdateChanged { update: d.clone() });
+                    }
+                },
+                Err(e) => eprintln!("WARNING: Failed to lock update descriptor: {}", e)
+            }
+        }
+
+        // no longer in flight
+        if let Ok(mut downloads) = self.downloads.lock() {
+            downloads.remove(&self.update_id);
+        }
+
+        result
+    }
+
+    async fn download(&self) -> Result<u64, UpdateError> {
+        // work on a copy so readers of the store aren't blocked for the whole transfer
+        let mut d = match self.descriptor.lock() {
+            Ok(descriptor) => descriptor.clone(),
+            Err(e) => {
+                return Err(UpdateError::Internal(format!("Failed to lock update descriptor: {}", e)));
+            }
+        };
+
+        let sanitized_url = UpdateStore::package_url(&d.mpak_download_url, &self.settings)?;
+
+        let client = reqwest::Client::new();
+
+        d.set_status(UpdateStatus::Downloading);
+        self.save(&d);
 
-                        println!("Marked update {} as applied", update_id);
+        // determine where to store the mpak - we will extract on apply
+        let update_folder = self.store_root.join(&d.mpak_id);
+        let package_path = update_folder.join(UpdateStore::PACKAGE_FILE_NAME);
+        let partial_path = update_folder.join(UpdateStore::PARTIAL_FILE_NAME);
+
+        println!("downloading {}", package_path.display());
+
+        // stream into the partial file, resuming from where we left off on failure
+        let mut attempt = 0;
+        loop {
+            let auth_header = self.auth_header().map_err(UpdateError::Internal)?;
+            match self.download_to_partial(&client, &sanitized_url, &auth_header, &partial_path, &mut d).await {
+                Ok(_) => break,
+                Err(DownloadFailure::Fatal(msg)) => {
+                    println!("{}", msg);
+                    return Err(UpdateError::DownloadFailed(msg));
+                },
+                Err(DownloadFailure::Unauthorized(msg)) => {
+                    attempt += 1;
+                    if !self.settings.use_authentication || attempt > self.settings.download_max_retries {
+                        println!("{}", msg);
+                        return Err(UpdateError::DownloadFailed(msg));
                     }
-                    Err(err) => {
-                        println!("ERROR: Failed to parse descriptor for {}: {:?}", update_id, err);
+                    metrics().download_retries.inc();
+                    println!("{}. Waiting for a new token (attempt {}/{})...",
+                        msg, attempt, self.settings.download_max_retries);
+                    self.wait_for_new_token(&auth_header).await;
+                },
+                Err(DownloadFailure::Retry(msg)) => {
+                    attempt += 1;
+                    if attempt > self.settings.download_max_retries {
+                        let msg = format!("{} (giving up after {} attempts)", msg, attempt);
+                        println!("{}", msg);
+                        return Err(UpdateError::DownloadFailed(msg));
                     }
+                    metrics().download_retries.inc();
+                    println!("{}. Retrying in {} seconds (attempt {}/{})...",
+                        msg, self.settings.connect_retry_seconds, attempt, self.settings.download_max_retries);
+                    tokio::time::sleep(Duration::from_secs(self.settings.connect_retry_seconds)).await;
                 }
             }
-            Err(err) => {
-                println!("ERROR: Failed to open descriptor file for {}: {:?}", update_id, err);
+        }
+
+        d.set_status(UpdateStatus::Verifying);
+        self.save(&d);
+
+        // make sure we got what the descriptor says we should have, and that it came from someone we trust
+        let verified = match UpdateStore::verify_package(&partial_path, &d) {
+            Ok(_) if self.settings.require_signed_updates => {
+                let signature_path = update_folder.join(UpdateStore::SIGNATURE_FILE_NAME);
+                let auth_header = self.auth_header().map_err(UpdateError::Internal)?;
+                match self.fetch_signature(&client, &sanitized_url, &auth_header, &d).await {
+                    Ok(signature) => UpdateStore::verify_and_store_signature(&self.settings, &partial_path, &signature, &signature_path),
+                    Err(err) => Err(err)
+                }
+            },
+            other => other
+        };
+        if let Err(err) = verified {
+            eprintln!("ERROR: Package verification failed for {}: {}", d.mpak_id, err);
+            match err {
+                UpdateError::BadPackage(_) => metrics().verify_failures.with_label_values(&["package"]).inc(),
+                UpdateError::BadSignature(_) => metrics().verify_failures.with_label_values(&["signature"]).inc(),
+                _ => {}
+            }
+            if let Err(e) = fs::remove_file(&partial_path) {
+                eprintln!("WARNING: Failed to remove invalid package '{}': {}", partial_path.display(), e);
+            }
+
+            d.retrieved = Some(false);
+            d.partial_offset = None;
+            d.set_error(UpdateStatus::Failed, err.to_string());
+            self.save(&d);
+
+            return Err(err);
+        }
+
+        // only now does the package take its real name
+        if let Err(e) = fs::rename(&partial_path, &package_path) {
+            return Err(UpdateError::Io(format!("Failed to move '{}' into place: {}", partial_path.display(), e)));
+        }
+
+        let size = match fs::metadata(&package_path) {
+            Ok(m) => m.len(),
+            Err(e) => {
+                return Err(UpdateError::Io(format!("Failed to read size of '{}': {}", package_path.display(), e)));
             }
+        };
+
+        // set the update as retrieved
+        d.partial_offset = None;
+        d.set_status(UpdateStatus::Downloaded);
+
+        // update file
+        self.save(&d);
+
+        // return the size?  file name?  something
+        Ok(size)
+    }
+
+    /// Get the detached signature for the package, from the descriptor or from `<url>.sig`
+    async fn fetch_signature(&self,
+        client: &reqwest::Client,
+        url: &str,
+        auth_header: &reqwest::header::HeaderValue,
+        d: &UpdateDescriptor) -> Result<Vec<u8>, UpdateError> {
+
+        if let Some(signature) = &d.signature {
+            return Ok(signature.as_bytes().to_vec());
+        }
+
+        let signature_url = format!("{}.sig", url);
+        println!("Fetching package signature from {}", signature_url);
+
+        let response = client
+            .get(&signature_url)
+            .header(reqwest::header::AUTHORIZATION, auth_header.clone())
+            .send()
+            .await
+            .map_err(|e| UpdateError::DownloadFailed(format!("Failed to download package signature: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(UpdateError::BadSignature(format!("Package is not signed (HTTP {} for {})", response.status(), signature_url)));
+        }
+
+        response.bytes().await
+            .map(|b| b.to_vec())
+            .map_err(|e| UpdateError::DownloadFailed(format!("Failed to download package signature: {}", e)))
+    }
+
+    /// Stream a package into its `.partial` file, resuming with a `Range` request if we already have some of it
+    ///
+    /// Only data that has been synced to disk is trusted on resume; that offset is checkpointed
+    /// into the descriptor's `info.json` so a daemon restart can pick up where it left off.
+    async fn download_to_partial(&self,
+        client: &reqwest::Client,
+        url: &str,
+        auth_header: &reqwest::header::HeaderValue,
+        partial_path: &Path,
+        d: &mut UpdateDescriptor) -> Result<(), DownloadFailure> {
+
+        let on_disk = fs::metadata(partial_path).map(|m| m.len()).unwrap_or(0);
+        let mut offset = std::cmp::min(on_disk, d.partial_offset.unwrap_or(0));
+        self.report_progress(offset);
+
+        if d.file_size != 0 && offset == u64::from(d.file_size) {
+            println!("Package already fully downloaded ({} bytes)", offset);
+            return Ok(());
+        }
+
+        let mut request = client
+            .get(url)
+            .header(reqwest::header::AUTHORIZATION, auth_header.clone());
+        if offset > 0 {
+            println!("Resuming download at byte {}", offset);
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+
+        let mut response = request.send().await
+            .map_err(|e| DownloadFailure::Retry(format!("Failed to download file: {}", e)))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            // whatever we have doesn't line up with what the server has; start over
+            let _ = fs::remove_file(partial_path);
+            d.partial_offset = None;
+            self.save(d);
+            return Err(DownloadFailure::Retry(format!("Server rejected resume at byte {}", offset)));
+        }
+        if !status.is_success() {
+            let msg = format!("Failed to download file: HTTP {}", status);
+            if status == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(DownloadFailure::Unauthorized(msg));
+            }
+            if status.is_server_error() {
+                return Err(DownloadFailure::Retry(msg));
+            }
+            return Err(DownloadFailure::Fatal(msg));
+        }
+
+        if offset > 0 {
+            let resumed_at = if status == reqwest::StatusCode::PARTIAL_CONTENT {
+                response.headers()
+                    .get(reqwest::header::CONTENT_RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(UpdateStore::parse_content_range_start)
+            } else {
+                None
+            };
+
+            if resumed_at != Some(offset) {
+                println!("Server did not honor the resume request; restarting download from the beginning");
+                offset = 0;
+                self.report_progress(offset);
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(partial_path)
+            .map_err(|e| DownloadFailure::Fatal(format!("Failed to create file '{}': {}", partial_path.display(), e)))?;
+        file.set_len(offset)
+            .and_then(|_| file.seek(SeekFrom::Start(offset)))
+            .map_err(|e| DownloadFailure::Fatal(format!("Failed to prepare file '{}': {}", partial_path.display(), e)))?;
+
+        let mut checkpoint = offset;
+
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    file.write_all(&chunk)
+                        .map_err(|e| DownloadFailure::Fatal(format!("Failed to write downloaded data to file: {}", e)))?;
+                    offset += chunk.len() as u64;
+                    metrics().download_bytes.inc_by(chunk.len() as u64);
+                    self.report_progress(offset);
+
+                    if offset - checkpoint >= UpdateStore::DOWNLOAD_CHECKPOINT_BYTES && file.sync_data().is_ok() {
+                        d.partial_offset = Some(offset);
+                        self.save(d);
+                        checkpoint = offset;
+                    }
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    // keep what we have for the next attempt
+                    if file.sync_data().is_ok() {
+                        d.partial_offset = Some(offset);
+                        self.save(d);
+                    }
+                    return Err(DownloadFailure::Retry(format!("Download interrupted at byte {}: {}", offset, e)));
+                }
+            }
+        }
+
+        file.sync_data()
+            .map_err(|e| DownloadFailure::Fatal(format!("Failed to flush '{}': {}", partial_path.display(), e)))?;
+        d.partial_offset = Some(offset);
+        self.save(d);
+
+        Ok(())
+    }
+
+    fn auth_header(&self) -> Result<reqwest::header::HeaderValue, String> {
+        let jwt = self.jwt.lock()
+            .map(|jwt| jwt.clone())
+            .map_err(|e| format!("Failed to lock token: {}", e))?;
+
+        reqwest::header::HeaderValue::from_str(&format!("Bearer {}", jwt))
+            .map_err(|e| format!("Failed to create auth header: {}", e))
+    }
+
+    /// Ask the update service to re-authenticate and give it a while to come back with a new token
+    async fn wait_for_new_token(&self, rejected: &reqwest::header::HeaderValue) {
+        self.reauth_requested.store(true, Ordering::SeqCst);
+
+        for _ in 0..UpdateStore::TOKEN_REFRESH_WAIT_SECONDS {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            if self.auth_header().is_ok_and(|header| header != *rejected) {
+                return;
+            }
+        }
+
+        println!("No new token after {} seconds", UpdateStore::TOKEN_REFRESH_WAIT_SECONDS);
+    }
+
+    fn report_progress(&self, bytes_received: u64) {
+        let progress = match self.downloads.lock() {
+            Ok(mut downloads) => match downloads.get_mut(&self.update_id) {
+                Some(progress) => {
+                    progress.bytes_received = bytes_received;
+                    progress.clone()
+                },
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        // don't flood listeners with an event per chunk
+        let last = self.last_progress_event.load(Ordering::Relaxed);
+        let finished = progress.total_bytes == Some(bytes_received);
+        if bytes_received >= last + UpdateStore::DOWNLOAD_PROGRESS_EVENT_BYTES || bytes_received < last || finished {
+            self.last_progress_event.store(bytes_received, Ordering::Relaxed);
+            self.events.publish(DaemonEvent::DownloadProgress {
+                mpak_id: self.update_id.clone(),
+                progress,
+            });
+        }
+    }
+
+    /// Publish our working copy of the descriptor to the store and to disk
+    fn save(&self, d: &UpdateDescriptor) {
+        let status_changed = match self.descriptor.lock() {
+            Ok(mut shared) => {
+                let changed = shared.status != d.status || shared.status_changed_at != d.status_changed_at;
+                *shared = d.clone();
+                changed
+            },
+            Err(e) => {
+                eprintln!("WARNING: Failed to lock update descriptor: {}", e);
+                false
+            }
+        };
+        UpdateStore::write_descriptor(&self.store_root, d);
+
+        // checkpoints are covered by progress events; only lifecycle changes are announced
+        if status_changed {
+            self.events.publish(DaemonEvent::UpdateChanged { update: d.clone() });
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,53 @@
+use mc_daemon::update_descriptor::{UpdateDescriptor, UpdateStatus};
+
+#[test]
+fn status_keeps_legacy_flags_test() {
+    let mut d = UpdateDescriptor::new("Status".to_string());
+    assert_eq!(d.status, UpdateStatus::Available);
+
+    d.set_error(UpdateStatus::Failed, "CRC mismatch");
+    assert_eq!(d.last_error.as_deref(), Some("CRC mismatch"));
+    assert!(d.status_changed_at.is_some());
+
+    d.set_status(UpdateStatus::Downloaded);
+    assert_eq!(d.retrieved, Some(true));
+    assert!(d.last_error.is_none());
+
+    d.set_status(UpdateStatus::Applied);
+    assert_eq!(d.applied, Some(true));
+
+    d.set_error(UpdateStatus::RolledBack, "App exited during health check");
+    assert_eq!(d.applied, Some(false));
+
+    let json = serde_json::to_value(&d).unwrap();
+    assert_eq!(json["status"], "RolledBack");
+    assert_eq!(json["lastError"], "App exited during health check");
+}
+
+#[test]
+fn recover_legacy_descriptor_test() {
+    // info.json written before there was a status field
+    let mut d = UpdateDescriptor::from_json(r#"{
+        "mpakId": "Legacy", "mpakDownloadUrl": "http://foo.bar", "targetDevices": null,
+        "publishedOn": "1/1/1980", "crc": "", "version": null, "fileSize": 1, "metadata": null,
+        "summary": null, "detail": null, "updateType": 1, "retrieved": true, "applied": null
+    }"#).unwrap();
+    assert_eq!(d.status, UpdateStatus::Available);
+
+    d.recover_status();
+    assert_eq!(d.status, UpdateStatus::Downloaded);
+}
+
+#[test]
+fn recover_interrupted_work_test() {
+    let mut downloading = UpdateDescriptor::new("Downloading".to_string());
+    downloading.set_status(UpdateStatus::Downloading);
+    downloading.recover_status();
+    assert_eq!(downloading.status, UpdateStatus::Available);
+
+    let mut applying = UpdateDescriptor::new("Applying".to_string());
+    applying.set_status(UpdateStatus::Applying);
+    applying.recover_status();
+    assert_eq!(applying.status, UpdateStatus::Failed);
+    assert!(applying.last_error.is_some());
+}
@@ -0,0 +1,37 @@
+use std::fs;
+
+use mc_daemon::device_id::{DeviceId, DeviceIdSource};
+
+#[test]
+fn parse_source_test() {
+    assert_eq!(Some(DeviceIdSource::MachineId), DeviceIdSource::parse("machine-id"));
+    assert_eq!(Some(DeviceIdSource::DmiSerial), DeviceIdSource::parse("DMI"));
+    assert_eq!(Some(DeviceIdSource::File("/etc/meadow/id".into())), DeviceIdSource::parse("file:/etc/meadow/id"));
+    assert_eq!(Some(DeviceIdSource::Env("MEADOW_DEVICE_ID".into())), DeviceIdSource::parse("env:MEADOW_DEVICE_ID"));
+    assert_eq!(Some(DeviceIdSource::Value("abc123".into())), DeviceIdSource::parse("value:abc123"));
+    assert_eq!(None, DeviceIdSource::parse("serial"));
+    assert_eq!(None, DeviceIdSource::parse("file:"));
+
+    let source = DeviceIdSource::parse("file:/etc/meadow/id").unwrap();
+    assert_eq!("file:/etc/meadow/id", source.to_string());
+}
+
+#[test]
+fn resolve_test() {
+    let dir = std::env::temp_dir().join("mc-daemon-device-id-tests");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("device-id");
+    fs::write(&path, "1c8150f752614dec80f88752256e829f\n").unwrap();
+
+    let id = DeviceId::resolve(&DeviceIdSource::File(path.clone())).unwrap();
+    assert_eq!("1C8150F752614DEC80F88752256E829F", id.as_str());
+
+    // SAFETY: no other test reads this variable
+    unsafe { std::env::set_var("MC_DAEMON_TEST_DEVICE_ID", " 1c8150f752614dec80f88752256e829f ") };
+    assert_eq!(id, DeviceId::resolve(&DeviceIdSource::Env("MC_DAEMON_TEST_DEVICE_ID".into())).unwrap());
+    assert_eq!(id, DeviceId::resolve(&DeviceIdSource::Value("1c8150f752614dec80f88752256e829f".into())).unwrap());
+
+    fs::write(&path, "\n").unwrap();
+    assert!(DeviceId::resolve(&DeviceIdSource::File(path)).is_err());
+    assert!(DeviceId::resolve(&DeviceIdSource::File(dir.join("missing"))).is_err());
+}
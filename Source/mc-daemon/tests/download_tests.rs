@@ -1,4 +1,4 @@
-use mc_daemon::{update_store::UpdateStore, update_descriptor::UpdateDescriptor, cloud_settings::CloudSettings};
+use mc_daemon::{update_error::UpdateError, update_store::UpdateStore, update_descriptor::UpdateDescriptor, cloud_settings::CloudSettings};
 
 #[tokio::test]
 async fn authentication_test() {
@@ -8,3 +8,71 @@ async fn authentication_test() {
 //    let success = store.authenticate_with_server().await;
     //let success = store.test_creds();
 }
+
+#[test]
+fn begin_download_tracks_progress_test() {
+    let mut settings = CloudSettings::default();
+    settings.update_store_path = std::env::temp_dir().join("mc-daemon-download-tests");
+    let mut store = UpdateStore::new(settings);
+    store.clear();
+
+    let mut desc = UpdateDescriptor::new("Progress".to_string());
+    desc.file_size = 4096;
+    store.add(std::sync::Arc::new(desc));
+
+    assert!(store.get_download_progress("Progress").is_none());
+
+    let job = store.begin_download(&"Progress".to_string()).unwrap();
+    let progress = store.get_download_progress("Progress").unwrap();
+    assert_eq!(0, progress.bytes_received);
+    assert_eq!(Some(4096), progress.total_bytes);
+
+    // only one download per update at a time
+    assert!(matches!(store.begin_download(&"Progress".to_string()), Err(UpdateError::DownloadInProgress(_))));
+    assert_eq!("Progress", job.update_id());
+
+    assert!(matches!(store.begin_download(&"Unknown".to_string()), Err(UpdateError::NotFound(_))));
+}
+
+#[test]
+fn partial_downloads_survive_restart_test() {
+    use mc_daemon::update_descriptor::UpdateStatus;
+
+    let mut settings = CloudSettings::default();
+    settings.update_store_path = std::env::temp_dir().join("mc-daemon-partial-download-tests");
+    let mut store = UpdateStore::new(settings.clone());
+    store.clear();
+
+    let mut started = UpdateDescriptor::new("Started".to_string());
+    started.status = UpdateStatus::Downloading;
+    store.add(std::sync::Arc::new(started));
+
+    let mut partial = UpdateDescriptor::new("Partial".to_string());
+    partial.partial_offset = Some(1024);
+    store.add(std::sync::Arc::new(partial));
+
+    store.add(std::sync::Arc::new(UpdateDescriptor::new("Available".to_string())));
+
+    // a restarted daemon picks up both, whatever auto_download_updates says
+    let store = UpdateStore::new(settings);
+    let mut resumable = store.get_partial_downloads();
+    resumable.sort();
+    assert_eq!(vec!["Partial".to_string(), "Started".to_string()], resumable);
+}
+
+#[test]
+fn package_url_test() {
+    let mut settings = CloudSettings::default();
+    settings.use_authentication = true;
+
+    assert_eq!("https://cdn.example.com/a.mpak", UpdateStore::package_url("cdn.example.com/a.mpak", &settings).unwrap());
+    assert_eq!("https://cdn.example.com/a.mpak", UpdateStore::package_url("https://cdn.example.com/a.mpak", &settings).unwrap());
+
+    // the token never goes out over plain http
+    assert!(matches!(UpdateStore::package_url("http://cdn.example.com/a.mpak", &settings), Err(UpdateError::DownloadFailed(_))));
+    assert!(UpdateStore::package_url("ftp://cdn.example.com/a.mpak", &settings).is_err());
+
+    // without authentication there's no token to leak, e.g. for a local test server
+    settings.use_authentication = false;
+    assert_eq!("http://localhost:8080/a.mpak", UpdateStore::package_url("http://localhost:8080/a.mpak", &settings).unwrap());
+}
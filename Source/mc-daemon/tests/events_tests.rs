@@ -0,0 +1,29 @@
+use std::sync::Arc;
+use mc_daemon::{cloud_settings::CloudSettings, events::DaemonEvent, update_descriptor::UpdateDescriptor, update_service::UpdateState, update_store::UpdateStore};
+
+#[test]
+fn add_publishes_update_added_test() {
+    let mut settings = CloudSettings::default();
+    settings.update_store_path = std::env::temp_dir().join("mc-daemon-events-tests");
+    let mut store = UpdateStore::new(settings);
+    store.clear();
+
+    let mut events = store.events().subscribe();
+    store.add(Arc::new(UpdateDescriptor::new("Pushed".to_string())));
+
+    match events.try_recv().unwrap() {
+        DaemonEvent::UpdateAdded { update } => assert_eq!("Pushed", update.mpak_id),
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert!(events.try_recv().is_err());
+}
+
+#[test]
+fn event_json_test() {
+    let event = DaemonEvent::StateChanged { state: UpdateState::Connected };
+    assert_eq!("stateChanged", event.name());
+
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["type"], "stateChanged");
+    assert_eq!(json["state"], "Connected");
+}
@@ -0,0 +1,157 @@
+use std::{fs::{self, File}, io::Write, path::{Path, PathBuf}};
+
+use mc_daemon::{update_error::UpdateError, update_store::UpdateStore};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("mc-daemon-extract-tests").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn build_package(dir: &Path, build: impl FnOnce(&mut ZipWriter<File>)) -> PathBuf {
+    let path = dir.join("update.mpak");
+    let mut zip = ZipWriter::new(File::create(&path).unwrap());
+    build(&mut zip);
+    zip.finish().unwrap();
+    path
+}
+
+#[test]
+fn extract_valid_package_test() {
+    let dir = test_dir("valid");
+    let package = build_package(&dir, |zip| {
+        zip.add_directory("app/", SimpleFileOptions::default()).unwrap();
+        zip.start_file("app/App.dll", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"app").unwrap();
+        zip.add_symlink("app/current", "App.dll", SimpleFileOptions::default()).unwrap();
+    });
+
+    let dest = dir.join("out");
+    assert!(UpdateStore::extract_archive(&package, &dest).is_ok());
+    assert!(dest.join("app").join("App.dll").is_file());
+}
+
+#[test]
+fn extract_rejects_parent_traversal_test() {
+    let dir = test_dir("traversal");
+    let package = build_package(&dir, |zip| {
+        zip.start_file("app/ok.txt", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"fine").unwrap();
+        zip.start_file("../../evil.txt", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+    });
+
+    let dest = dir.join("out");
+    let err = UpdateStore::extract_archive(&package, &dest).unwrap_err();
+    assert!(matches!(err, UpdateError::BadPackage(ref msg) if msg.contains("outside the destination")));
+
+    // nothing at all is extracted from a bad package
+    assert!(!dest.join("app").exists());
+    assert!(!dir.parent().unwrap().join("evil.txt").exists());
+}
+
+#[test]
+fn extract_rejects_absolute_path_test() {
+    let dir = test_dir("absolute");
+    let package = build_package(&dir, |zip| {
+        zip.start_file("/etc/cron.d/x", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+    });
+
+    assert!(UpdateStore::extract_archive(&package, &dir.join("out")).is_err());
+}
+
+#[test]
+fn extract_rejects_escaping_symlink_test() {
+    let dir = test_dir("symlink");
+    let package = build_package(&dir, |zip| {
+        zip.add_symlink("app/etc", "../../etc", SimpleFileOptions::default()).unwrap();
+    });
+
+    let err = UpdateStore::extract_archive(&package, &dir.join("out")).unwrap_err();
+    assert!(matches!(err, UpdateError::BadPackage(ref msg) if msg.contains("symlink")));
+}
+
+#[test]
+fn extract_rejects_symlink_chain_escape_test() {
+    // each target looks harmless on its own, but l2 resolves through l1 to the parent of the destination
+    for (name, reversed) in [("chain", false), ("chain-reversed", true)] {
+        let dir = test_dir(name);
+        let package = build_package(&dir, |zip| {
+            let links = [("a/l1", ".."), ("l2", "a/l1/..")];
+            let ordered: Vec<_> = if reversed { links.iter().rev().collect() } else { links.iter().collect() };
+            for (link, target) in ordered {
+                zip.add_symlink(*link, *target, SimpleFileOptions::default()).unwrap();
+            }
+        });
+
+        let dest = dir.join("out");
+        let err = UpdateStore::extract_archive(&package, &dest).unwrap_err();
+        assert!(matches!(err, UpdateError::BadPackage(ref msg) if msg.contains("outside the package")));
+        assert!(!dest.join("l2").exists());
+    }
+}
+
+#[test]
+fn extract_rejects_write_through_symlink_test() {
+    let dir = test_dir("through-symlink");
+    let package = build_package(&dir, |zip| {
+        zip.add_symlink("app/lib", "data", SimpleFileOptions::default()).unwrap();
+        zip.start_file("app/lib/file.so", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"lib").unwrap();
+    });
+
+    let err = UpdateStore::extract_archive(&package, &dir.join("out")).unwrap_err();
+    assert!(matches!(err, UpdateError::BadPackage(ref msg) if msg.contains("through a symlink")));
+}
+
+/// The zip writer won't store a device file, so patch the unix mode in the central directory
+fn set_unix_mode(package: &Path, mode: u32) {
+    let mut data = fs::read(package).unwrap();
+    let header = data.windows(4).position(|w| w == [0x50, 0x4b, 0x01, 0x02]).unwrap();
+    data[header + 38..header + 42].copy_from_slice(&(mode << 16).to_le_bytes());
+    fs::write(package, data).unwrap();
+}
+
+#[test]
+fn extract_rejects_device_file_test() {
+    let dir = test_dir("device");
+    let package = build_package(&dir, |zip| {
+        zip.start_file("app/tty", SimpleFileOptions::default().unix_permissions(0o644)).unwrap();
+    });
+    // character device
+    set_unix_mode(&package, 0o020644);
+
+    let err = UpdateStore::extract_archive(&package, &dir.join("out")).unwrap_err();
+    assert!(matches!(err, UpdateError::BadPackage(ref msg) if msg.contains("device file")));
+}
+
+#[test]
+fn extract_preserves_modes_and_symlinks_test() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = test_dir("modes");
+    let package = build_package(&dir, |zip| {
+        zip.start_file("app/run.sh", SimpleFileOptions::default().unix_permissions(0o755)).unwrap();
+        zip.write_all(b"#!/bin/sh\n").unwrap();
+        zip.start_file("app/secret.conf", SimpleFileOptions::default().unix_permissions(0o600)).unwrap();
+        zip.write_all(b"key=value").unwrap();
+        zip.start_file("app/setuid", SimpleFileOptions::default().unix_permissions(0o4755)).unwrap();
+        zip.write_all(b"nope").unwrap();
+        zip.add_symlink("app/start", "run.sh", SimpleFileOptions::default()).unwrap();
+    });
+
+    let dest = dir.join("out");
+    UpdateStore::extract_archive(&package, &dest).unwrap();
+
+    let mode = |name: &str| fs::metadata(dest.join("app").join(name)).unwrap().permissions().mode() & 0o7777;
+    assert_eq!(mode("run.sh"), 0o755);
+    assert_eq!(mode("secret.conf"), 0o600);
+    assert_eq!(mode("setuid"), 0o755);
+
+    let link = dest.join("app").join("start");
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("run.sh"));
+}
@@ -0,0 +1,59 @@
+use std::fs;
+use mc_daemon::{cloud_settings::CloudSettings, health::{HealthResponse, HealthStatus}, update_service::ServiceStatus};
+
+fn settings(name: &str) -> CloudSettings {
+    let store = std::env::temp_dir().join("mc-daemon-health-tests").join(name);
+    let _ = fs::remove_dir_all(&store);
+    fs::create_dir_all(&store).unwrap();
+
+    let mut settings = CloudSettings::default();
+    settings.update_store_path = store;
+    settings.enable_mqtt_listener = true;
+    settings
+}
+
+#[test]
+fn readiness_test() {
+    let mut settings = settings("ready");
+    let mut status = ServiceStatus::new();
+
+    // the service loop hasn't come round yet
+    let readiness = HealthResponse::ready(&settings, &status);
+    assert!(!readiness.is_ok());
+    assert_eq!(HealthStatus::Failing, readiness.components.as_ref().unwrap().update_service.status);
+
+    status.heartbeat();
+    let readiness = HealthResponse::ready(&settings, &status);
+    assert!(readiness.is_ok());
+    let json = serde_json::to_value(&readiness).unwrap();
+    assert_eq!("ok", json["status"]);
+    assert_eq!("ok", json["components"]["updateStore"]["status"]);
+    assert_eq!("failing", json["components"]["cloud"]["status"]);
+    assert!(fs::read_dir(&settings.update_store_path).unwrap().next().is_none(), "probe file left behind");
+
+    settings.ready_requires_cloud = true;
+    assert!(!HealthResponse::ready(&settings, &status).is_ok());
+    status.set_broker_connected(true);
+    assert!(HealthResponse::ready(&settings, &status).is_ok());
+
+    settings.update_store_path = settings.update_store_path.join("missing");
+    let readiness = HealthResponse::ready(&settings, &status);
+    assert!(!readiness.is_ok());
+    assert_eq!(HealthStatus::Failing, readiness.components.unwrap().update_store.status);
+}
+
+#[test]
+fn readiness_without_listener_test() {
+    let mut settings = settings("no-listener");
+    settings.enable_mqtt_listener = false;
+    settings.ready_requires_cloud = true;
+
+    // no service thread and no cloud connection to wait for
+    let readiness = HealthResponse::ready(&settings, &ServiceStatus::new());
+    assert!(readiness.is_ok());
+    let json = serde_json::to_value(&readiness).unwrap();
+    assert_eq!("disabled", json["components"]["updateService"]["status"]);
+    assert_eq!("disabled", json["components"]["cloud"]["status"]);
+
+    assert_eq!(r#"{"status":"ok"}"#, serde_json::to_string(&HealthResponse::live()).unwrap());
+}
@@ -0,0 +1,38 @@
+use actix_web::{App, HttpResponse, middleware, test, web};
+use mc_daemon::metrics::{self, ApplyTimer, metrics};
+
+#[actix_web::test]
+async fn request_metrics_test() {
+    let app = test::init_service(
+        App::new()
+            .wrap(middleware::from_fn(metrics::track_requests))
+            .route("/api/updates/{id}", web::get().to(|| async { HttpResponse::NotFound().finish() }))
+    ).await;
+
+    for id in ["ABC", "DEF"] {
+        let req = test::TestRequest::get().uri(&format!("/api/updates/{}", id)).to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let text = metrics().render();
+    // both paths land on the one route pattern
+    assert!(text.contains(r#"meadow_daemon_http_requests_total{method="GET",route="/api/updates/{id}",status="404"} 2"#), "{}", text);
+    assert!(text.contains(r#"meadow_daemon_http_request_duration_seconds_count{method="GET",route="/api/updates/{id}"} 2"#), "{}", text);
+}
+
+#[actix_web::test]
+async fn apply_timer_test() {
+    let applies = &metrics().applies;
+    let succeeded = applies.with_label_values(&["succeeded"]).get();
+    let failed = applies.with_label_values(&["failed"]).get();
+    let timed = metrics().apply_duration.get_sample_count();
+
+    ApplyTimer::start().succeeded();
+    // bailing out without calling succeeded() is a failure
+    drop(ApplyTimer::start());
+
+    assert_eq!(succeeded + 1, applies.with_label_values(&["succeeded"]).get());
+    assert_eq!(failed + 1, applies.with_label_values(&["failed"]).get());
+    assert_eq!(timed + 1, metrics().apply_duration.get_sample_count());
+    assert!(metrics().render().contains("# TYPE meadow_daemon_apply_duration_seconds histogram"));
+}
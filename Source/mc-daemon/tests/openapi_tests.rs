@@ -0,0 +1,55 @@
+use actix_web::{App, http::{Method, StatusCode}, test::{TestRequest, call_service, init_service}};
+use mc_daemon::{rest_openapi::ApiDoc, rest_server::RestServer};
+use serde_json::Value;
+
+#[test]
+fn openapi_document_test() {
+    let doc: Value = serde_json::from_str(&ApiDoc::json()).unwrap();
+    assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+
+    // every route registered in RestServer::routes
+    for (path, method) in [
+        ("/api/info", "get"), ("/api/status", "get"), ("/api/cloud/reconnect", "post"),
+        ("/api/provision", "get"), ("/api/provision", "post"),
+        ("/api/updates", "get"), ("/api/updates", "delete"), ("/api/updates/confirm", "post"),
+        ("/api/updates/{id}", "get"), ("/api/updates/{id}", "put"),
+        ("/api/apply", "put"), ("/api/rollback", "post"),
+        ("/api/releases", "get"), ("/api/releases/{id}", "put"),
+        ("/api/events", "get"), ("/api/files", "get"), ("/api/files/{path}", "get"),
+        ("/api/openapi.json", "get"), ("/metrics", "get"), ("/healthz", "get"), ("/readyz", "get"),
+    ] {
+        assert!(doc["paths"][path][method].is_object(), "{} {} is not documented", method, path);
+    }
+
+    let schemas = &doc["components"]["schemas"];
+    for name in ["UpdateAction", "ApplyAction", "ServiceInfo", "FileListResponse", "UpdateDescriptor"] {
+        assert!(schemas[name].is_object(), "schema {} is missing", name);
+    }
+    assert!(schemas["UpdateDescriptor"]["properties"]["mpakId"].is_object());
+    assert_eq!(doc["paths"]["/api/updates/{id}"]["put"]["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/UpdateAction");
+}
+
+#[actix_web::test]
+async fn documented_paths_are_routed_test() {
+    let doc: Value = serde_json::from_str(&ApiDoc::json()).unwrap();
+    // no app data, so handlers that need the store or settings fail with 500 before doing anything
+    let app = init_service(App::new().configure(RestServer::routes)).await;
+
+    let paths = doc["paths"].as_object().unwrap();
+    for (path, operations) in paths {
+        let uri = path.replace("{id}", "ABC").replace("{path}", "app");
+        assert!(!uri.contains('{'), "no sample value for a parameter in {}", path);
+
+        for method in operations.as_object().unwrap().keys() {
+            let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+            let req = TestRequest::default().method(method.clone()).uri(&uri).to_request();
+            let status = call_service(&app, req).await.status();
+            assert!(status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED,
+                "{} {} is documented but not routed ({})", method, path, status);
+        }
+    }
+
+    let req = TestRequest::get().uri("/api/nothing-here").to_request();
+    assert_eq!(StatusCode::NOT_FOUND, call_service(&app, req).await.status());
+}
@@ -0,0 +1,61 @@
+#![cfg(unix)]
+
+use std::{fs, os::unix::fs::MetadataExt};
+
+use actix_web::{App, HttpResponse, HttpServer, middleware, web};
+use mc_daemon::rest_auth::{self, PeerCredentials, PeerPolicy};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+async fn request(socket: &std::path::Path, method: &str) -> String {
+    let mut stream = tokio::net::UnixStream::connect(socket).await.unwrap();
+    stream.write_all(format!("{} /api/apply HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", method).as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[test]
+fn peer_policy_test() {
+    let peer = PeerCredentials { uid: 1000, gid: 100, pid: None };
+    assert!(PeerPolicy::default().allows(&peer));
+    assert!(PeerPolicy::new(vec![1000], vec![]).allows(&peer));
+    assert!(PeerPolicy::new(vec![0], vec![100]).allows(&peer));
+    assert!(!PeerPolicy::new(vec![0], vec![0]).allows(&peer));
+}
+
+#[actix_web::test]
+async fn unix_socket_peer_check_test() {
+    let dir = std::env::temp_dir().join("mc-daemon-peer-tests");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let me = fs::metadata(&dir).unwrap();
+
+    for (policy, expected) in [
+        (PeerPolicy::new(vec![me.uid()], vec![]), "200 OK"),
+        (PeerPolicy::new(vec![me.uid() + 1], vec![me.gid() + 1]), "403 Forbidden"),
+    ] {
+        let socket = dir.join("meadow.sock");
+        let _ = fs::remove_file(&socket);
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(policy.clone()))
+                .service(web::scope("api")
+                    .wrap(middleware::from_fn(rest_auth::require_peer))
+                    .route("/apply", web::get().to(HttpResponse::Ok))
+                    .route("/apply", web::put().to(HttpResponse::Ok)))
+        })
+            .on_connect(rest_auth::record_peer_credentials)
+            .workers(1)
+            .listen_uds(std::os::unix::net::UnixListener::bind(&socket).unwrap()).unwrap()
+            .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        // GET is never restricted
+        assert!(request(&socket, "GET").await.starts_with("HTTP/1.1 200 OK"));
+        assert!(request(&socket, "PUT").await.starts_with(&format!("HTTP/1.1 {}", expected)));
+
+        handle.stop(false).await;
+    }
+}
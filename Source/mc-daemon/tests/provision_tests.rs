@@ -0,0 +1,118 @@
+use std::{fs, net::TcpListener, sync::{Arc, Mutex}};
+
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, dev::ServerHandle, http::StatusCode, web};
+use actix_web::test::{TestRequest, call_service, init_service, read_body_json};
+use mc_daemon::{cloud_settings::CloudSettings, crypto::Crypto, device_id::{DeviceId, DeviceIdSource}, rest_server::RestServer, update_service::ServiceStatus};
+use serde_json::{json, Value};
+
+/// (Authorization header, body) of every registration the fake server received
+type Registrations = Arc<Mutex<Vec<(Option<String>, Value)>>>;
+
+/// A stand-in for Meadow.Cloud's device registration endpoint, answering every call with `status`
+fn start_auth_server(status: StatusCode) -> (String, Registrations, ServerHandle) {
+    let registrations: Registrations = Arc::default();
+    let seen = registrations.clone();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let server = HttpServer::new(move || {
+        let seen = seen.clone();
+        App::new().route("/api/devices/register", web::post().to(move |req: HttpRequest, body: web::Json<Value>| {
+            let seen = seen.clone();
+            async move {
+                let authorization = req.headers().get("Authorization")
+                    .and_then(|h| h.to_str().ok())
+                    .map(str::to_string);
+                seen.lock().unwrap().push((authorization, body.into_inner()));
+                HttpResponse::build(status).finish()
+            }
+        }))
+    })
+        .workers(1)
+        .listen(listener).unwrap()
+        .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    (url, registrations, handle)
+}
+
+fn test_settings(name: &str, auth_server: &str) -> CloudSettings {
+    let dir = std::env::temp_dir().join("mc-daemon-provision-tests").join(name);
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut settings = CloudSettings::default();
+    settings.device_key_path = Some(dir.join("device_key"));
+    settings.auth_server_address = Some(auth_server.to_string());
+    settings.enable_mqtt_listener = false;
+    Crypto::generate_private_key(settings.auth_key_path()).unwrap();
+    settings
+}
+
+async fn provision(settings: &CloudSettings) -> actix_web::dev::ServiceResponse {
+    let app = init_service(
+        App::new()
+            .app_data(web::Data::new(Arc::new(Mutex::new(ServiceStatus::new()))))
+            .app_data(web::Data::new(DeviceId::resolve(&DeviceIdSource::Value("abc123".to_string())).unwrap()))
+            .app_data(web::Data::new(settings.clone()))
+            .configure(RestServer::routes)
+    ).await;
+
+    let req = TestRequest::post().uri("/api/provision")
+        .set_json(json!({"token": "user-token", "orgId": "org1", "collectionId": "col1", "name": "bench-7"}))
+        .to_request();
+    call_service(&app, req).await
+}
+
+#[actix_web::test]
+async fn provision_registers_device_test() {
+    let (url, registrations, handle) = start_auth_server(StatusCode::OK);
+    let settings = test_settings("registered", &url);
+    let public_key = Crypto::get_public_key_openssh(Some(settings.auth_key_path())).unwrap();
+
+    let response = provision(&settings).await;
+    assert_eq!(StatusCode::OK, response.status());
+    let body: Value = read_body_json(response).await;
+    assert_eq!("ABC123", body["deviceId"]);
+    assert_eq!(public_key, body["publicKey"]);
+
+    {
+        let registrations = registrations.lock().unwrap();
+        assert_eq!(1, registrations.len());
+        let (authorization, sent) = &registrations[0];
+        assert_eq!(Some("Bearer user-token"), authorization.as_deref());
+        assert_eq!(json!({
+            "id": "ABC123",
+            "orgId": "org1",
+            "collectionId": "col1",
+            "name": "bench-7",
+            "pubKey": public_key,
+        }), *sent);
+    }
+
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn provision_rejected_test() {
+    let (url, registrations, handle) = start_auth_server(StatusCode::FORBIDDEN);
+    let settings = test_settings("rejected", &url);
+
+    let response = provision(&settings).await;
+    assert_eq!(StatusCode::BAD_GATEWAY, response.status());
+    let body: Value = read_body_json(response).await;
+    assert_eq!("upstream_error", body["code"]);
+    assert_eq!(1, registrations.lock().unwrap().len());
+
+    handle.stop(false).await;
+}
+
+#[actix_web::test]
+async fn provision_without_device_key_test() {
+    let mut settings = CloudSettings::default();
+    settings.device_key_path = Some(std::env::temp_dir().join("mc-daemon-provision-tests").join("no-such-key"));
+    settings.auth_server_address = Some("http://127.0.0.1:9".to_string());
+
+    let response = provision(&settings).await;
+    assert_eq!(StatusCode::CONFLICT, response.status());
+}
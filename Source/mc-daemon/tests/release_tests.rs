@@ -0,0 +1,84 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use mc_daemon::{cloud_settings::CloudSettings, release_store::ReleaseStore};
+
+fn test_settings(name: &str, keep: u32) -> (CloudSettings, PathBuf) {
+    let root = std::env::temp_dir().join("mc-daemon-release-tests").join(name);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    let mut settings = CloudSettings::default();
+    settings.releases_path = root.join("releases");
+    settings.keep_releases = keep;
+    (settings, root)
+}
+
+fn stage(root: &Path, version: &str) -> PathBuf {
+    let staging = root.join("staging");
+    fs::create_dir_all(&staging).unwrap();
+    fs::write(staging.join("version.txt"), version).unwrap();
+    staging
+}
+
+#[test]
+fn install_adopts_existing_app_test() {
+    let (settings, root) = test_settings("adopt", 3);
+    let app_dir = root.join("app");
+    fs::create_dir_all(&app_dir).unwrap();
+    fs::write(app_dir.join("version.txt"), "1").unwrap();
+
+    let releases = ReleaseStore::new(&settings);
+    assert_eq!(releases.install(&app_dir, &stage(&root, "2"), "v2").unwrap(), "v2");
+
+    // the app directory now follows `current`
+    assert!(fs::symlink_metadata(&app_dir).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_to_string(app_dir.join("version.txt")).unwrap(), "2");
+
+    let list = releases.list();
+    assert_eq!(list.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["initial", "v2"]);
+    assert!(list[1].active);
+
+    // switch back and forth
+    releases.activate("initial").unwrap();
+    assert_eq!(fs::read_to_string(app_dir.join("version.txt")).unwrap(), "1");
+    releases.activate("v2").unwrap();
+    assert_eq!(fs::read_to_string(app_dir.join("version.txt")).unwrap(), "2");
+    assert!(releases.activate("v9").is_err());
+}
+
+#[test]
+fn install_prunes_old_releases_test() {
+    let (settings, root) = test_settings("prune", 2);
+    let app_dir = root.join("app");
+
+    let releases = ReleaseStore::new(&settings);
+    for v in ["v1", "v2", "v3", "v4"] {
+        releases.install(&app_dir, &stage(&root, v), v).unwrap();
+    }
+
+    // current plus two previous versions
+    let ids: Vec<String> = releases.list().into_iter().map(|r| r.id).collect();
+    assert_eq!(ids, vec!["v2", "v3", "v4"]);
+    assert!(!settings.releases_path.join("v1").exists());
+
+    // re-installing an existing id gets a fresh directory
+    assert_eq!(releases.install(&app_dir, &stage(&root, "v4"), "v4").unwrap(), "v4-2");
+}
+
+#[test]
+fn roll_back_to_previous_release_test() {
+    let (settings, root) = test_settings("rollback", 3);
+    let app_dir = root.join("app");
+
+    let releases = ReleaseStore::new(&settings);
+    releases.install(&app_dir, &stage(&root, "1"), "v1").unwrap();
+    releases.install(&app_dir, &stage(&root, "2"), "v2").unwrap();
+    assert_eq!(releases.previous().as_deref(), Some("v1"));
+
+    assert_eq!(releases.roll_back().unwrap(), "v1");
+    assert_eq!(fs::read_to_string(app_dir.join("version.txt")).unwrap(), "1");
+    assert!(!settings.releases_path.join("v2").exists());
+
+    // nothing older than v1 left
+    assert!(releases.roll_back().is_err());
+}
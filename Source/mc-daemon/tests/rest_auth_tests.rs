@@ -0,0 +1,148 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::{App, HttpResponse, http::{Method, StatusCode}, middleware, web};
+use actix_web::test::{TestRequest, call_service, init_service};
+use mc_daemon::{cloud_settings::CloudSettings, rest_auth::{self, ApiToken, RestAuth, Scope}, rest_server::RestServer};
+
+fn auth() -> RestAuth {
+    RestAuth::new(true, vec![
+        ApiToken::parse("app read,download s3cret").unwrap(),
+        ApiToken::parse("admin all t0ps3cret").unwrap(),
+    ])
+}
+
+#[actix_web::test]
+async fn route_scope_test() {
+    let mut settings = CloudSettings::default();
+    settings.meadow_root = std::env::temp_dir().join("mc-daemon-rest-auth-tests");
+    std::fs::create_dir_all(&settings.meadow_root).unwrap();
+
+    let app = init_service(
+        App::new()
+            .app_data(web::Data::new(auth()))
+            .app_data(web::Data::new(settings))
+            .configure(RestServer::routes)
+    ).await;
+
+    // scopes follow the route that was matched, however the path is spelled
+    for (method, uri) in [(Method::POST, "/api/rollback"), (Method::POST, "/api/%72ollback"), (Method::GET, "/api/%66iles/x"), (Method::POST, "/api/provision")] {
+        let req = TestRequest::default().method(method.clone()).uri(uri)
+            .insert_header(("Authorization", "Bearer s3cret")).to_request();
+        assert_eq!(StatusCode::FORBIDDEN, call_service(&app, req).await.status(), "{} {}", method, uri);
+    }
+
+    let req = TestRequest::get().uri("/api/%6fpenapi.json")
+        .insert_header(("Authorization", "Bearer s3cret")).to_request();
+    assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+
+    let req = TestRequest::get().uri("/api/%66iles")
+        .insert_header(("Authorization", "Bearer t0ps3cret")).to_request();
+    assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+}
+
+#[test]
+fn parse_token_test() {
+    let token = ApiToken::parse("app read,apply s3cret").unwrap();
+    assert_eq!("app", token.name);
+    assert!(token.allows(Scope::Apply));
+    assert!(!token.allows(Scope::Files));
+
+    assert!(ApiToken::parse("app read").is_err());
+    assert!(ApiToken::parse("app write s3cret").is_err());
+}
+
+#[actix_web::test]
+async fn middleware_test() {
+    let app = init_service(
+        App::new()
+            .app_data(web::Data::new(auth()))
+            .service(web::scope("api")
+                .wrap(middleware::from_fn(rest_auth::require_token))
+                .service(web::resource("/updates")
+                    .wrap(middleware::from_fn(rest_auth::require_read))
+                    .route(web::get().to(HttpResponse::Ok)))
+                .service(web::resource("/apply")
+                    .wrap(middleware::from_fn(rest_auth::require_apply))
+                    .route(web::put().to(HttpResponse::Ok))))
+    ).await;
+
+    let req = TestRequest::get().uri("/api/updates").to_request();
+    assert_eq!(StatusCode::UNAUTHORIZED, call_service(&app, req).await.status());
+
+    let req = TestRequest::get().uri("/api/updates")
+        .insert_header(("Authorization", "Bearer wrong")).to_request();
+    assert_eq!(StatusCode::UNAUTHORIZED, call_service(&app, req).await.status());
+
+    let req = TestRequest::get().uri("/api/updates")
+        .insert_header(("Authorization", "Bearer s3cret")).to_request();
+    assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+
+    let req = TestRequest::put().uri("/api/apply")
+        .insert_header(("Authorization", "Bearer s3cret")).to_request();
+    assert_eq!(StatusCode::FORBIDDEN, call_service(&app, req).await.status());
+
+    // HMAC signatures cover the method, path, time, nonce and body
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let body = r#"{"pid":42}"#;
+    let signature = RestAuth::sign("t0ps3cret", &Method::PUT, "/api/apply", now, "n1", body.as_bytes());
+    let req = TestRequest::put().uri("/api/apply")
+        .insert_header(("Authorization", format!("HMAC-SHA256 admin:{}:n1:{}", now, signature)))
+        .set_payload(body).to_request();
+    assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+
+    let signature = RestAuth::sign("t0ps3cret", &Method::PUT, "/api/apply", now, "n2", body.as_bytes());
+    let req = TestRequest::put().uri("/api/apply")
+        .insert_header(("Authorization", format!("HMAC-SHA256 admin:{}:n2:{}", now, signature)))
+        .set_payload(r#"{"pid":1}"#).to_request();
+    assert_eq!(StatusCode::UNAUTHORIZED, call_service(&app, req).await.status());
+
+    let old = now - 3600;
+    let signature = RestAuth::sign("t0ps3cret", &Method::GET, "/api/updates", old, "n3", b"");
+    let req = TestRequest::get().uri("/api/updates")
+        .insert_header(("Authorization", format!("HMAC-SHA256 admin:{}:n3:{}", old, signature))).to_request();
+    assert_eq!(StatusCode::UNAUTHORIZED, call_service(&app, req).await.status());
+
+    // the old three-part header has no nonce
+    let req = TestRequest::get().uri("/api/updates")
+        .insert_header(("Authorization", format!("HMAC-SHA256 admin:{}:{}", now, signature))).to_request();
+    assert_eq!(StatusCode::UNAUTHORIZED, call_service(&app, req).await.status());
+}
+
+#[test]
+fn hmac_replay_test() {
+    let auth = auth();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let signature = RestAuth::sign("t0ps3cret", &Method::POST, "/api/rollback", now, "abc123", b"{}");
+    let header = format!("HMAC-SHA256 admin:{}:abc123:{}", now, signature);
+
+    assert!(auth.authenticate(&Method::POST, "/api/rollback", Some(&header), b"{}").is_ok());
+    // the same signed request again, even through another worker's clone, is refused
+    let err = auth.clone().authenticate(&Method::POST, "/api/rollback", Some(&header), b"{}").unwrap_err();
+    assert!(err.contains("already been used"), "{}", err);
+
+    // a bad signature doesn't use up the nonce
+    let header = format!("HMAC-SHA256 admin:{}:fresh:{}", now, signature);
+    assert!(auth.authenticate(&Method::POST, "/api/rollback", Some(&header), b"{}").is_err());
+    let signature = RestAuth::sign("t0ps3cret", &Method::POST, "/api/rollback", now, "fresh", b"{}");
+    let header = format!("HMAC-SHA256 admin:{}:fresh:{}", now, signature);
+    assert!(auth.authenticate(&Method::POST, "/api/rollback", Some(&header), b"{}").is_ok());
+
+    let header = format!("HMAC-SHA256 admin:{}:bad:nonce:{}", now, signature);
+    assert!(auth.authenticate(&Method::POST, "/api/rollback", Some(&header), b"{}").is_err());
+}
+
+#[actix_web::test]
+async fn disabled_test() {
+    let app = init_service(
+        App::new()
+            .app_data(web::Data::new(RestAuth::default()))
+            .service(web::scope("api")
+                .wrap(middleware::from_fn(rest_auth::require_token))
+                .service(web::resource("/apply")
+                    .wrap(middleware::from_fn(rest_auth::require_apply))
+                    .route(web::put().to(HttpResponse::Ok))))
+    ).await;
+
+    let req = TestRequest::put().uri("/api/apply").to_request();
+    assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+}
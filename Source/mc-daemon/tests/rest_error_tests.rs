@@ -0,0 +1,40 @@
+use actix_web::{ResponseError, body::to_bytes, http::StatusCode};
+use mc_daemon::{rest_error::ApiError, update_error::UpdateError};
+use serde_json::Value;
+
+async fn body_of(error: ApiError) -> (StatusCode, Value) {
+    let response = error.error_response();
+    let status = response.status();
+    let body = to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[actix_web::test]
+async fn update_error_response_test() {
+    let (status, body) = body_of(UpdateError::NotDownloaded("ABC".to_string()).into()).await;
+    assert_eq!(StatusCode::CONFLICT, status);
+    assert_eq!("update_not_downloaded", body["code"]);
+    assert_eq!("Update ABC has not been downloaded", body["message"]);
+    assert_eq!("ABC", body["details"]["id"]);
+
+    let (status, body) = body_of(UpdateError::BadPackage("Package CRC mismatch".to_string()).into()).await;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+    assert_eq!("bad_package", body["code"]);
+    assert!(body["details"].is_null());
+
+    let (status, _) = body_of(UpdateError::NotFound("ABC".to_string()).into()).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+}
+
+#[actix_web::test]
+async fn request_error_response_test() {
+    let (status, body) = body_of(ApiError::PidNotFound { pid: 4242, reason: "No such file or directory".to_string() }).await;
+    assert_eq!(StatusCode::NOT_FOUND, status);
+    assert_eq!("pid_not_found", body["code"]);
+    assert_eq!(4242, body["details"]["pid"]);
+
+    let (status, body) = body_of(ApiError::UnknownAction("launch".to_string())).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+    assert_eq!("unknown_action", body["code"]);
+    assert_eq!("launch", body["details"]["action"]);
+}
@@ -0,0 +1,66 @@
+use std::{fs, path::PathBuf, thread, time::Duration};
+
+use mc_daemon::{cloud_settings::CloudSettings, update_error::UpdateError, update_store::UpdateStore};
+
+fn test_settings(name: &str) -> (CloudSettings, PathBuf) {
+    let root = std::env::temp_dir().join("mc-daemon-rollback-tests").join(name);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    let mut settings = CloudSettings::default();
+    settings.update_store_path = root.join("updates");
+    settings.staging_path = root.join("staging");
+    settings.rollback_path = root.join("rollback");
+    (settings, root)
+}
+
+#[test]
+fn rollback_without_previous_version_test() {
+    let (settings, root) = test_settings("none");
+    let store = UpdateStore::new(settings);
+
+    let app_dir = root.join("app");
+    let err = store.rollback_update(&app_dir, &app_dir.join("App"), None, &None).unwrap_err();
+    assert!(matches!(err, UpdateError::NoPreviousVersion(_)));
+    assert!(err.to_string().contains("No previous version"));
+}
+
+#[test]
+fn rollback_restores_previous_version_test() {
+    let (settings, root) = test_settings("restore");
+
+    let app_dir = root.join("app");
+    fs::create_dir_all(&app_dir).unwrap();
+    fs::write(app_dir.join("version.txt"), "2").unwrap();
+    fs::create_dir_all(&settings.rollback_path).unwrap();
+    fs::write(settings.rollback_path.join("version.txt"), "1").unwrap();
+
+    let store = UpdateStore::new(settings.clone());
+    assert!(store.rollback_update(&app_dir, &app_dir.join("App"), None, &None).is_ok());
+
+    // the swap happens in the background
+    for _ in 0..50 {
+        if fs::read_to_string(app_dir.join("version.txt")).ok().as_deref() == Some("1") {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    assert_eq!(fs::read_to_string(app_dir.join("version.txt")).unwrap(), "1");
+    assert!(!settings.staging_path.exists());
+}
+
+#[tokio::test]
+async fn apply_extracted_refused_when_signing_required_test() {
+    let (mut settings, root) = test_settings("unsigned-apply");
+    settings.temp_extract_path = root.join("update");
+    fs::create_dir_all(settings.temp_extract_path.join("app")).unwrap();
+    settings.require_signed_updates = true;
+
+    let store = UpdateStore::new(settings.clone());
+    let app_dir = root.join("app");
+    let err = store.apply_extracted_update(&app_dir, &app_dir.join("App"), std::process::id() as i32, &None).await.unwrap_err();
+    assert!(matches!(err, UpdateError::BadSignature(_)));
+    // nothing was touched
+    assert!(settings.temp_extract_path.join("app").is_dir());
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::Path;
+
+use mc_daemon::{cloud_settings::{CloudSettings, HealthCheckMode, KeyWrapAlgorithm}, device_id::DeviceIdSource, rest_auth::Scope};
+
+fn write_config(name: &str, contents: &str) -> String {
+    let dir = std::env::temp_dir().join("mc-daemon-settings-tests");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn health_check_settings_test() {
+    let path = write_config("health.conf", "\
+health_check_mode http
+health_check_seconds 45
+health_check_url http://127.0.0.1:8080/health
+");
+
+    let settings = CloudSettings::from_file(&path);
+    assert_eq!(settings.health_check_mode, HealthCheckMode::Http);
+    assert_eq!(settings.health_check_seconds, 45);
+    assert_eq!(settings.health_check_url.as_deref(), Some("http://127.0.0.1:8080/health"));
+}
+
+#[test]
+fn health_check_defaults_test() {
+    let path = write_config("health-bad.conf", "health_check_mode sometimes\n");
+
+    let settings = CloudSettings::from_file(&path);
+    assert_eq!(settings.health_check_mode, HealthCheckMode::None);
+    assert_eq!(settings.health_check_seconds, 30);
+    assert!(settings.health_check_url.is_none());
+}
+
+#[test]
+fn auth_retry_settings_test() {
+    let path = write_config("auth-retry.conf", "auth_retry_minutes 30\n");
+    assert_eq!(CloudSettings::from_file(&path).auth_retry_minutes, 30);
+
+    let path = write_config("auth-retry-bad.conf", "auth_retry_minutes soon\n");
+    assert_eq!(CloudSettings::from_file(&path).auth_retry_minutes, 0);
+}
+
+#[test]
+fn device_key_path_settings_test() {
+    let path = write_config("device-key-unset.conf", "ssh_key_path /home/meadow/.ssh/id_rsa\n");
+    let settings = CloudSettings::from_file(&path);
+    assert!(settings.device_key_path.is_none());
+    assert_eq!(settings.auth_key_path(), Path::new("/home/meadow/.ssh/id_rsa"));
+
+    let path = write_config("device-key.conf", "ssh_key_path /home/meadow/.ssh/id_rsa\ndevice_key_path /var/lib/meadow/device_key\n");
+    assert_eq!(CloudSettings::from_file(&path).auth_key_path(), Path::new("/var/lib/meadow/device_key"));
+}
+
+#[test]
+fn key_wrap_algorithm_settings_test() {
+    assert_eq!(CloudSettings::default().key_wrap_algorithm, KeyWrapAlgorithm::RsaPkcs1);
+
+    let path = write_config("key-wrap.conf", "key_wrap_algorithm rsa-oaep\n");
+    assert_eq!(CloudSettings::from_file(&path).key_wrap_algorithm, KeyWrapAlgorithm::RsaOaep);
+
+    // ECDH device keys aren't supported; they fall back to the default rather than fail at login
+    let path = write_config("key-wrap-ecdh.conf", "key_wrap_algorithm x25519\n");
+    assert_eq!(CloudSettings::from_file(&path).key_wrap_algorithm, KeyWrapAlgorithm::RsaPkcs1);
+
+    let path = write_config("key-wrap-bad.conf", "key_wrap_algorithm rot13\n");
+    assert_eq!(CloudSettings::from_file(&path).key_wrap_algorithm, KeyWrapAlgorithm::RsaPkcs1);
+}
+
+#[test]
+fn device_id_source_settings_test() {
+    assert_eq!(CloudSettings::default().device_id_source, DeviceIdSource::MachineId);
+
+    let path = write_config("device-id.conf", "device_id_source env:MEADOW_DEVICE_ID\n");
+    assert_eq!(CloudSettings::from_file(&path).device_id_source, DeviceIdSource::Env("MEADOW_DEVICE_ID".into()));
+
+    let path = write_config("device-id-bad.conf", "device_id_source serial\n");
+    assert_eq!(CloudSettings::from_file(&path).device_id_source, DeviceIdSource::MachineId);
+}
+
+#[test]
+fn rest_api_token_settings_test() {
+    let path = write_config("rest-auth.conf", "rest_api_auth yes\nrest_api_token app read,download s3cret\nrest_api_token admin all t0ps3cret\nrest_api_token broken read\n");
+    let settings = CloudSettings::from_file(&path);
+    assert!(settings.rest_api_auth);
+    assert_eq!(settings.rest_api_tokens.len(), 2);
+    assert_eq!(settings.rest_api_tokens[0].name, "app");
+    assert!(!settings.rest_api_tokens[0].allows(Scope::Apply));
+    assert!(settings.rest_api_tokens[1].allows(Scope::Apply));
+}
+
+#[test]
+fn rest_api_socket_settings_test() {
+    let settings = CloudSettings::default();
+    assert!(settings.rest_api_enable_tcp);
+    assert!(settings.rest_api_socket_path.is_none());
+    assert_eq!(settings.rest_api_socket_mode, 0o660);
+
+    let path = write_config("rest-socket.conf", "rest_api_enable_tcp no\nrest_api_socket_path /run/meadow/daemon.sock\nrest_api_socket_mode 0600\nrest_api_allowed_uids 0, 1000,bob\nrest_api_allowed_gids 1001\n");
+    let settings = CloudSettings::from_file(&path);
+    assert!(!settings.rest_api_enable_tcp);
+    assert_eq!(settings.rest_api_socket_path.as_deref(), Some(Path::new("/run/meadow/daemon.sock")));
+    assert_eq!(settings.rest_api_socket_mode, 0o600);
+    assert_eq!(settings.rest_api_allowed_uids, vec![0, 1000]);
+    assert_eq!(settings.rest_api_allowed_gids, vec![1001]);
+}
+
+#[test]
+fn rest_api_tls_settings_test() {
+    let path = write_config("rest-tls.conf", "rest_api_port 8443\nrest_api_tls_cert_path /etc/meadow/tls/server.crt\nrest_api_tls_key_path /etc/meadow/tls/server.key\n");
+    let settings = CloudSettings::from_file(&path);
+    assert_eq!(settings.rest_api_port, 8443);
+    assert_eq!(settings.rest_api_tls_cert_path.as_deref(), Some(Path::new("/etc/meadow/tls/server.crt")));
+    assert_eq!(settings.rest_api_tls_key_path.as_deref(), Some(Path::new("/etc/meadow/tls/server.key")));
+    assert!(settings.rest_api_tls_client_ca_path.is_none());
+
+    let path = write_config("rest-port-bad.conf", "rest_api_port 70000\n");
+    assert_eq!(CloudSettings::from_file(&path).rest_api_port, 5000);
+}
@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::{App, http::StatusCode, web};
+use actix_web::test::{TestRequest, call_service, init_service, read_body_json};
+use mc_daemon::{cloud_settings::CloudSettings, rest_server::RestServer, update_service::{ServiceStatus, UpdateState}};
+use serde_json::Value;
+
+#[test]
+fn service_status_test() {
+    let mut status = ServiceStatus::new();
+    assert_eq!(UpdateState::Dead, status.state);
+    assert!(status.state_changed_at.is_none());
+
+    status.set_state(UpdateState::Connected);
+    status.set_broker_connected(true);
+    status.set_error("MQTT connection failed");
+    status.message_received();
+
+    let json = serde_json::to_value(&status).unwrap();
+    assert_eq!(json["state"], "Connected");
+    assert_eq!(json["brokerConnected"], true);
+    assert_eq!(json["lastError"], "MQTT connection failed");
+    assert!(json["stateChangedAt"].is_u64());
+    assert!(json["lastErrorAt"].is_u64());
+    assert!(json["lastMessageAt"].is_u64());
+    assert_eq!(json["authFailCount"], 0);
+}
+
+#[test]
+fn reconnect_only_after_auth_failure_test() {
+    let mut status = ServiceStatus::new();
+    status.set_state(UpdateState::Idle);
+    assert!(status.request_reconnect().is_err());
+
+    status.set_state(UpdateState::AuthenticationFailed);
+    assert!(status.request_reconnect().is_ok());
+}
+
+#[actix_web::test]
+async fn reconnect_endpoint_test() {
+    let mut settings = CloudSettings::default();
+    settings.enable_mqtt_listener = true;
+    let mut status = ServiceStatus::new();
+    status.set_state(UpdateState::AuthenticationFailed);
+    let status = Arc::new(Mutex::new(status));
+
+    let app = init_service(
+        App::new()
+            .app_data(web::Data::new(status.clone()))
+            .app_data(web::Data::new(settings))
+            .configure(RestServer::routes)
+    ).await;
+
+    // success comes back in the same {code, message, details} shape as errors
+    let req = TestRequest::post().uri("/api/cloud/reconnect").to_request();
+    let response = call_service(&app, req).await;
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    let body: Value = read_body_json(response).await;
+    assert_eq!("reconnect_requested", body["code"]);
+    assert!(body["message"].is_string());
+
+    status.lock().unwrap().set_state(UpdateState::Idle);
+    let req = TestRequest::post().uri("/api/cloud/reconnect").to_request();
+    let response = call_service(&app, req).await;
+    assert_eq!(StatusCode::CONFLICT, response.status());
+    let body: Value = read_body_json(response).await;
+    assert_eq!("reconnect_rejected", body["code"]);
+}
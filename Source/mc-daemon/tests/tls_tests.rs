@@ -0,0 +1,63 @@
+use std::{fs, net::TcpListener, path::{Path, PathBuf}};
+
+use actix_web::{App, HttpResponse, HttpServer, web};
+use mc_daemon::{cloud_settings::CloudSettings, rest_tls};
+
+fn tls_file(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/tls").join(name)
+}
+
+fn client(identity: bool) -> reqwest::Client {
+    let ca = reqwest::Certificate::from_pem(&fs::read(tls_file("ca.crt")).unwrap()).unwrap();
+    let mut builder = reqwest::Client::builder().add_root_certificate(ca);
+    if identity {
+        let mut pem = fs::read(tls_file("client.crt")).unwrap();
+        pem.extend(fs::read(tls_file("client.key")).unwrap());
+        builder = builder.identity(reqwest::Identity::from_pem(&pem).unwrap());
+    }
+    builder.build().unwrap()
+}
+
+#[test]
+fn tls_settings_test() {
+    let mut settings = CloudSettings::default();
+    assert_eq!(settings.rest_api_port, 5000);
+    assert!(rest_tls::server_config(&settings).unwrap().is_none());
+
+    settings.rest_api_tls_cert_path = Some(tls_file("server.crt"));
+    assert!(rest_tls::server_config(&settings).is_err());
+
+    settings.rest_api_tls_key_path = Some(tls_file("server.key"));
+    settings.rest_api_tls_client_ca_path = Some(tls_file("ca.crt"));
+    assert!(rest_tls::server_config(&settings).unwrap().is_some());
+
+    // a key is not a certificate
+    settings.rest_api_tls_client_ca_path = Some(tls_file("client.key"));
+    assert!(rest_tls::server_config(&settings).is_err());
+}
+
+#[actix_web::test]
+async fn client_certificate_test() {
+    for require_client_cert in [false, true] {
+        let client_ca = tls_file("ca.crt");
+        let config = rest_tls::load_server_config(&tls_file("server.crt"), &tls_file("server.key"),
+            require_client_cert.then_some(client_ca.as_path())).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://localhost:{}/api/info", listener.local_addr().unwrap().port());
+        let server = HttpServer::new(|| App::new().route("/api/info", web::get().to(HttpResponse::Ok)))
+            .workers(1)
+            .listen_rustls_0_23(listener, config).unwrap()
+            .run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let response = client(true).get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let anonymous = client(false).get(&url).send().await;
+        assert_eq!(anonymous.is_ok(), !require_client_cert);
+
+        handle.stop(false).await;
+    }
+}
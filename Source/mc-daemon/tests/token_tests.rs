@@ -0,0 +1,19 @@
+use base64::{Engine, engine::general_purpose};
+use mc_daemon::update_service::UpdateService;
+
+fn make_jwt(claims: &str) -> String {
+    format!("{}.{}.signature",
+        general_purpose::URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","typ":"JWT"}"#),
+        general_purpose::URL_SAFE_NO_PAD.encode(claims))
+}
+
+#[test]
+fn token_expiry_test() {
+    let jwt = make_jwt(r#"{"oid":"org","exp":1893456000}"#);
+    assert_eq!(Some(1893456000), UpdateService::token_expiry(&jwt));
+
+    let jwt = make_jwt(r#"{"oid":"org"}"#);
+    assert_eq!(None, UpdateService::token_expiry(&jwt));
+
+    assert_eq!(None, UpdateService::token_expiry("not-a-jwt"));
+}
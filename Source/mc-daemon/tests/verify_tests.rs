@@ -0,0 +1,120 @@
+use std::{fs, path::PathBuf};
+
+use mc_daemon::{cloud_settings::CloudSettings, package_verifier::PackageVerifier, update_error::UpdateError, update_store::UpdateStore, update_descriptor::UpdateDescriptor};
+
+fn write_package(name: &str, data: &[u8]) -> PathBuf {
+    let dir = std::env::temp_dir().join("mc-daemon-verify-tests");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn verify_matching_package_test() {
+    let data = b"not really a zip, but good enough for a checksum";
+    let path = write_package("good.mpak", data);
+
+    let mut desc = UpdateDescriptor::new("Good".to_string());
+    desc.file_size = data.len() as u32;
+    desc.crc = format!("0x{:08X}", crc32fast::hash(data));
+
+    assert!(UpdateStore::verify_package(&path, &desc).is_ok());
+}
+
+#[test]
+fn verify_truncated_package_test() {
+    let data = b"truncated download";
+    let path = write_package("short.mpak", &data[..9]);
+
+    let mut desc = UpdateDescriptor::new("Short".to_string());
+    desc.file_size = data.len() as u32;
+    desc.crc = format!("{:08x}", crc32fast::hash(data));
+
+    let err = UpdateStore::verify_package(&path, &desc).unwrap_err();
+    assert!(matches!(err, UpdateError::BadPackage(ref msg) if msg.contains("size mismatch")));
+}
+
+#[test]
+fn verify_corrupt_package_test() {
+    let data = b"corrupted download";
+    let path = write_package("corrupt.mpak", b"corrupted d0wnload");
+
+    let mut desc = UpdateDescriptor::new("Corrupt".to_string());
+    desc.file_size = data.len() as u32;
+    desc.crc = format!("{:08x}", crc32fast::hash(data));
+
+    let err = UpdateStore::verify_package(&path, &desc).unwrap_err();
+    assert!(matches!(err, UpdateError::BadPackage(ref msg) if msg.contains("CRC mismatch")));
+}
+
+fn write_signing_key(name: &str, key: &ed25519_dalek::SigningKey) -> PathBuf {
+    use ed25519_dalek::pkcs8::{EncodePublicKey, spki::der::pem::LineEnding};
+    let pem = key.verifying_key().to_public_key_pem(LineEnding::LF).unwrap();
+    write_package(name, pem.as_bytes())
+}
+
+#[test]
+fn verify_signed_package_test() {
+    use base64::Engine;
+    use ed25519_dalek::Signer;
+
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let key_path = write_signing_key("release.pem", &signing_key);
+    let verifier = PackageVerifier::load(&[key_path]).unwrap();
+
+    let path = write_package("signed.mpak", b"signed package contents");
+    let digest = PackageVerifier::sha256_file(&path).unwrap();
+    let signature = signing_key.sign(&digest).to_bytes();
+
+    assert!(verifier.verify_file(&path, &signature).is_ok());
+
+    // base64 text (as found in a .sig file) works too
+    let encoded = base64::engine::general_purpose::STANDARD.encode(signature);
+    assert!(verifier.verify_file(&path, encoded.as_bytes()).is_ok());
+
+    let tampered = write_package("tampered.mpak", b"signed package c0ntents");
+    assert!(verifier.verify_file(&tampered, &signature).is_err());
+}
+
+#[test]
+fn verify_untrusted_signature_test() {
+    use ed25519_dalek::Signer;
+
+    let trusted_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let other_key = ed25519_dalek::SigningKey::from_bytes(&[9u8; 32]);
+    let key_path = write_signing_key("trusted.pem", &trusted_key);
+    let verifier = PackageVerifier::load(&[key_path]).unwrap();
+
+    let path = write_package("untrusted.mpak", b"package from someone else");
+    let digest = PackageVerifier::sha256_file(&path).unwrap();
+    let signature = other_key.sign(&digest).to_bytes();
+
+    assert!(verifier.verify_file(&path, &signature).is_err());
+    assert!(PackageVerifier::load(&[]).is_err());
+}
+
+#[test]
+fn rejected_signature_is_not_stored_test() {
+    use ed25519_dalek::Signer;
+
+    let trusted_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+    let other_key = ed25519_dalek::SigningKey::from_bytes(&[9u8; 32]);
+    let mut settings = CloudSettings::default();
+    settings.trusted_signing_keys = vec![write_signing_key("store-trusted.pem", &trusted_key)];
+
+    let path = write_package("store-sig.mpak", b"package waiting for its signature");
+    let digest = PackageVerifier::sha256_file(&path).unwrap();
+    let signature_path = path.with_extension("mpak.sig");
+
+    // a stale signature from an earlier attempt goes too
+    fs::write(&signature_path, b"stale").unwrap();
+    let bad = other_key.sign(&digest).to_bytes();
+    let err = UpdateStore::verify_and_store_signature(&settings, &path, &bad, &signature_path).unwrap_err();
+    assert!(matches!(err, UpdateError::BadSignature(_)));
+    assert!(!signature_path.exists());
+
+    let good = trusted_key.sign(&digest).to_bytes();
+    assert!(UpdateStore::verify_and_store_signature(&settings, &path, &good, &signature_path).is_ok());
+    assert_eq!(good.to_vec(), fs::read(&signature_path).unwrap());
+}